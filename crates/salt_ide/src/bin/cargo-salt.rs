@@ -1,6 +1,18 @@
 #![feature(rustc_private)]
 
+use salt_ide::plugin::{capabilities, print_result};
+
 fn main() {
   env_logger::init();
-  rustc_plugin::cli_main(salt_ide::plugin::print_result::SaltPlugin);
-}
\ No newline at end of file
+  // like `-V`, answered before rustc_plugin looks for a cargo project
+  let salt_args = std::env::args().take_while(|arg| arg != "--").collect::<Vec<_>>();
+  if salt_args.iter().any(|arg| arg == "--print-schema") {
+    print_result::print_schema();
+    return;
+  }
+  if salt_args.iter().any(|arg| arg == "--capabilities") {
+    capabilities::print_capabilities();
+    return;
+  }
+  rustc_plugin::cli_main(print_result::SaltPlugin);
+}
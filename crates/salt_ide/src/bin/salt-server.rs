@@ -0,0 +1,21 @@
+#![feature(rustc_private)]
+
+use clap::Parser;
+use salt_ide::plugin::server::{self, Server, ServerArgs};
+
+fn main() {
+  env_logger::init();
+  // cargo runs the server as rustc's wrapper while it records each crate's invocation
+  if let Some(record) = std::env::var_os(server::RECORD_VAR) {
+    std::process::exit(server::record_invocation(record.as_ref(), std::env::args().skip(1)));
+  }
+  // and runs itself to compile each crate a request is about
+  if let Ok(plugin_args) = std::env::var(server::COMPILE_VAR) {
+    std::process::exit(server::compile_crate(&plugin_args, std::env::args().skip(1)));
+  }
+  let args = ServerArgs::parse();
+  if let Err(e) = Server::new(args).and_then(|mut server| server.serve()) {
+    eprintln!("salt-server stopped: {}", e);
+    std::process::exit(1);
+  }
+}
@@ -2,12 +2,24 @@
 
 #![feature(rustc_private)]
 
+extern crate rustc_ast;
+extern crate rustc_borrowck;
+extern crate rustc_data_structures;
 extern crate rustc_driver;
+extern crate rustc_errors;
+extern crate rustc_infer;
 extern crate rustc_interface;
+extern crate rustc_lint_defs;
 extern crate rustc_middle;
+extern crate rustc_mir_dataflow;
+extern crate rustc_query_system;
 extern crate rustc_session;
+extern crate rustc_trait_selection;
 
 extern crate rustc_hir;
+extern crate rustc_hir_analysis;
+extern crate rustc_hir_typeck;
+extern crate rustc_index;
 extern crate rustc_span;
 
 pub mod plugin;
\ No newline at end of file
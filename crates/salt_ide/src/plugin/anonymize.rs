@@ -0,0 +1,105 @@
+use clap::ValueEnum;
+use hmac::{Hmac, Mac};
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::def_id::DefId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+// how items and files are identified in the output
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Identifiers {
+    /// Anonymized hashes only
+    #[default]
+    Hashed,
+    /// Def paths, file paths, spans and type names instead of hashes
+    Paths,
+    /// Hashes, with the readable names next to them
+    Both,
+}
+
+impl Identifiers {
+    pub fn hashed(self) -> bool {
+        self != Identifiers::Paths
+    }
+
+    pub fn readable(self) -> bool {
+        self != Identifiers::Hashed
+    }
+}
+
+static IDENTIFIERS: OnceLock<Identifiers> = OnceLock::new();
+
+// chosen once per driver process, before any output is produced
+pub fn set_identifiers(identifiers: Identifiers) {
+    let _ = IDENTIFIERS.set(identifiers);
+}
+
+pub(crate) fn identifiers() -> Identifiers {
+    IDENTIFIERS.get().copied().unwrap_or_default()
+}
+
+// set by the extension to the participant's secret, so ids can't be recovered by hashing guessed names
+pub const KEY_VAR: &str = "SALT_PARTICIPANT_KEY";
+
+// hex digits kept from each digest
+const ID_LEN: usize = 16;
+const FILE_LEN: usize = 8;
+
+fn participant_key() -> Option<&'static [u8]> {
+    static KEY: OnceLock<Option<Vec<u8>>> = OnceLock::new();
+    KEY.get_or_init(|| {
+        std::env::var(KEY_VAR)
+            .ok()
+            .filter(|key| !key.is_empty())
+            .map(String::into_bytes)
+    })
+    .as_deref()
+}
+
+// HMAC-SHA256 under the participant's key, or plain SHA-256 when no key is set
+pub fn hash_string(input: &str) -> String {
+    let digest = match participant_key() {
+        Some(key) => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+            mac.update(input.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        }
+        None => Sha256::digest(input.as_bytes()).to_vec(),
+    };
+    let mut hex = to_hex(&digest);
+    hex.truncate(ID_LEN);
+    hex
+}
+
+// hashes the DefId to an anonymized string that is the same in every session: the crate name and
+// the path part of its DefPathHash. The DefPathHash's crate part mixes in the rustc version,
+// and DefIds themselves depend on crate numbering.
+pub(crate) fn hash_id(tcx: TyCtxt, def_id: &DefId) -> String {
+    if identifiers().hashed() {
+        let local_hash = tcx.def_path_hash(*def_id).local_hash().as_u64();
+        hash_string(&format!("{}::{:016x}", tcx.crate_name(def_id.krate), local_hash))
+    }
+    else {
+        tcx.def_path_str(*def_id)
+    }
+}
+
+// the id of the crate being compiled, from its name
+pub(crate) fn crate_id(tcx: TyCtxt) -> String {
+    let crate_name = tcx.crate_name(LOCAL_CRATE).to_string();
+    if identifiers().hashed() { hash_string(&crate_name) } else { crate_name }
+}
+
+// the extension's hash for file names: SHA-256 truncated to 8 hex digits, unkeyed so
+// records can be joined with the extension's logs
+pub fn hash_file_name(file_name: &str) -> String {
+    let mut hex = to_hex(&Sha256::digest(file_name.as_bytes()));
+    hex.truncate(FILE_LEN);
+    hex
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,454 @@
+use rustc_borrowck::consumers::{
+    self, BodyWithBorrowckFacts, BorrowData, BorrowIndex, ConsumerOptions, PlaceConflictBias,
+    TwoPhaseActivation,
+};
+use rustc_hir::def_id::LocalDefId;
+use rustc_index::IndexVec;
+use rustc_middle::mir::visit::{
+    MutatingUseContext, NonMutatingUseContext, NonUseContext, PlaceContext, Visitor,
+};
+use rustc_middle::mir::{
+    Body, BorrowCheckResult, BorrowKind, Local, Location, MutBorrowKind, Place, ProjectionElem,
+};
+use rustc_middle::ty::TyCtxt;
+use rustc_middle::util::Providers;
+use rustc_span::source_map::SourceMap;
+use rustc_span::Span;
+use rustc_utils::{cache::Cache, PlaceExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::plugin::anonymize::hash_id;
+use crate::plugin::diagnostics::{self, Captured};
+
+thread_local! {
+    static MIR_BODIES: Cache<LocalDefId, Option<BodyWithBorrowckFacts<'static>>> = Cache::default();
+}
+
+// source span in the same shape as rustc's json diagnostics (1-based lines and columns)
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug, Clone)]
+pub struct SpanJson {
+    pub(crate) file_name: String,
+    pub(crate) line_start: usize,
+    pub(crate) column_start: usize,
+    pub(crate) line_end: usize,
+    pub(crate) column_end: usize,
+}
+
+impl SpanJson {
+    pub(crate) fn in_body(source_map: &SourceMap, body: &Body, span: Span) -> Self {
+        SpanJson::new(source_map, user_span(body, span))
+    }
+
+    pub(crate) fn new(source_map: &SourceMap, span: Span) -> Self {
+        let lo = source_map.lookup_char_pos(span.lo());
+        let hi = source_map.lookup_char_pos(span.hi());
+        SpanJson {
+            file_name: lo.file.name.prefer_local().to_string(),
+            line_start: lo.line,
+            column_start: lo.col.0 + 1,
+            line_end: hi.line,
+            column_end: hi.col.0 + 1,
+        }
+    }
+}
+
+// one loan that borrowck reported as invalidated while still in scope
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct BorrowErrorJson {
+    pub(crate) def_id: String,
+    pub(crate) borrowed_place: String,
+    pub(crate) borrow_kind: String,
+    pub(crate) access_kind: String,
+    pub(crate) borrow_span: SpanJson,
+    pub(crate) access_span: SpanJson,
+    pub(crate) later_use_span: Option<SpanJson>,
+    pub(crate) region_lines: (usize, usize),
+}
+
+// one loan in a function that borrowck accepted, with the lines where it is live
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct LoanJson {
+    pub(crate) span: SpanJson,
+    pub(crate) borrowed_place: String,
+    pub(crate) borrow_kind: String,
+    pub(crate) mutable: bool,
+    pub(crate) live_lines: Vec<usize>,
+}
+
+// installed from `Callbacks::config` so that borrowck keeps its facts around for us
+pub fn override_queries(_session: &rustc_session::Session, providers: &mut Providers) {
+    providers.mir_borrowck = |tcx, def_id| mir_borrowck(tcx, def_id, ConsumerOptions::RegionInferenceContext);
+}
+
+// the same, also keeping the outlives constraints that Polonius would be given,
+// without running Polonius itself
+pub fn override_queries_with_input_facts(_session: &rustc_session::Session, providers: &mut Providers) {
+    providers.mir_borrowck = |tcx, def_id| mir_borrowck(tcx, def_id, ConsumerOptions::PoloniusInputFacts);
+}
+
+fn mir_borrowck(tcx: TyCtxt<'_>, def_id: LocalDefId, options: ConsumerOptions) -> &BorrowCheckResult<'_> {
+    let (input_body, _) = tcx.mir_promoted(def_id);
+    let skipped = {
+        let input_body = input_body.borrow();
+        input_body.should_skip() || input_body.tainted_by_errors.is_some()
+    };
+
+    // rustc makes up a result for tainted bodies, so there are no facts to keep
+    let body_with_facts = (!skipped).then(|| consumers::get_body_with_borrowck_facts(tcx, def_id, options));
+    // SAFETY: the reader casts the 'static lifetime back to 'tcx before using it.
+    let body_with_facts: Option<BodyWithBorrowckFacts<'static>> =
+        unsafe { std::mem::transmute(body_with_facts) };
+    MIR_BODIES.with(|cache| {
+        cache.get(&def_id, |_| body_with_facts);
+    });
+
+    let mut providers = Providers::default();
+    rustc_borrowck::provide(&mut providers);
+    (providers.mir_borrowck)(tcx, def_id)
+}
+
+// runs borrowck on a body and returns its facts, or None if the body didn't type-check
+pub(crate) fn body_with_facts<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    def_id: LocalDefId,
+) -> Option<&'tcx BodyWithBorrowckFacts<'tcx>> {
+    // same order as rustc's analysis pass, unsafety checking is what frees the THIR
+    tcx.ensure_ok().check_unsafety(def_id);
+    let _ = tcx.mir_borrowck(def_id);
+    MIR_BODIES.with(|cache| {
+        let body = cache.get(&def_id, |_| None);
+        // SAFETY: the cache lives as long as the compiler session, so it outlives 'tcx.
+        unsafe {
+            std::mem::transmute::<
+                Option<&BodyWithBorrowckFacts<'static>>,
+                Option<&'tcx BodyWithBorrowckFacts<'tcx>>,
+            >(body.as_ref())
+        }
+    })
+}
+
+// collects every borrow error in the crate that borrowck reported to `emitted`
+pub(crate) fn borrowck_errors(tcx: TyCtxt, emitted: &Captured) -> Vec<BorrowErrorJson> {
+    tcx.hir_body_owners().flat_map(|def_id| body_borrowck_errors(tcx, def_id, emitted)).collect()
+}
+
+pub(crate) fn body_borrowck_errors(tcx: TyCtxt, def_id: LocalDefId, emitted: &Captured) -> Vec<BorrowErrorJson> {
+    let source_map = tcx.sess.source_map();
+    let mut errors = Vec::new();
+
+    let Some(facts) = body_with_facts(tcx, def_id) else { return errors };
+    let body = &facts.body;
+    let regions = loan_regions(facts);
+
+    for (loan, location) in error_loans(tcx, facts, &regions, emitted) {
+        let access_span = body.source_info(location).span;
+        let borrow = &facts.borrow_set[loan];
+        let borrowed_place = borrow.borrowed_place();
+        let region = &regions[loan];
+
+        errors.push(BorrowErrorJson {
+            def_id: hash_id(tcx, &def_id.to_def_id()),
+            borrowed_place: place_name(tcx, body, borrowed_place),
+            borrow_kind: borrow_kind(borrow.kind()),
+            access_kind: access_kind(tcx, body, borrowed_place, location),
+            borrow_span: SpanJson::in_body(
+                source_map,
+                body,
+                body.source_info(borrow.reserve_location()).span,
+            ),
+            access_span: SpanJson::in_body(source_map, body, access_span),
+            later_use_span: later_use(body, region, access_span)
+                .map(|loc| SpanJson::in_body(source_map, body, body.source_info(loc).span)),
+            region_lines: region_lines(source_map, body, region),
+        });
+    }
+    errors
+}
+
+// each loan NLL has in scope where an access conflicts with it, with the location of the access,
+// keeping the conflicts at the borrow or the access of an error borrowck reported to `emitted`
+pub(crate) fn error_loans<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    facts: &BodyWithBorrowckFacts<'tcx>,
+    regions: &IndexVec<BorrowIndex, Vec<Location>>,
+    emitted: &Captured,
+) -> Vec<(BorrowIndex, Location)> {
+    let body = &facts.body;
+    let error_spans = diagnostics::error_spans(emitted);
+    let reported = |span: Span| {
+        let span = user_span(body, span);
+        error_spans.iter().any(|error| user_span(body, *error).overlaps(span))
+    };
+
+    let mut conflicts = Vec::new();
+    for (idx, borrow) in facts.borrow_set.location_map().values().enumerate() {
+        let loan = BorrowIndex::from_usize(idx);
+        let borrow_span = body.source_info(borrow.reserve_location()).span;
+        for location in &regions[loan] {
+            if *location == borrow.reserve_location() || !conflicts_with(tcx, body, borrow, *location) {
+                continue;
+            }
+            if reported(borrow_span) || reported(body.source_info(*location).span) {
+                conflicts.push((*location, loan));
+            }
+        }
+    }
+    conflicts.sort();
+
+    // two-phase borrows are invalidated at both reservation and activation,
+    // so only the first of several overlapping accesses to a loan is reported
+    let mut reported: HashMap<BorrowIndex, Vec<Span>> = HashMap::new();
+    let mut loans = Vec::new();
+    for (location, loan) in conflicts {
+        let access_span = body.source_info(location).span;
+        let spans = reported.entry(loan).or_default();
+        if spans.iter().any(|span| span.overlaps(access_span)) {
+            continue;
+        }
+        spans.push(access_span);
+        loans.push((loan, location));
+    }
+    loans
+}
+
+// whether the access at `location` conflicts with a loan that's in scope there, by the rules
+// rustc's borrowck checks accesses with: reads only conflict with mutable loans once they're
+// activated, everything else with any loan
+fn conflicts_with<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, borrow: &BorrowData<'tcx>, location: Location) -> bool {
+    let borrowed = borrow.borrowed_place();
+    match access_context(tcx, body, borrowed, location) {
+        None
+        | Some(PlaceContext::NonMutatingUse(NonMutatingUseContext::FakeBorrow | NonMutatingUseContext::PlaceMention)) => false,
+        // a local going out of scope doesn't end loans of what it points to
+        Some(PlaceContext::NonUse(NonUseContext::StorageDead)) => {
+            !borrowed.projection.iter().any(|elem| elem == ProjectionElem::Deref)
+        }
+        Some(PlaceContext::NonMutatingUse(NonMutatingUseContext::Move)) | Some(PlaceContext::MutatingUse(..)) => true,
+        Some(PlaceContext::NonMutatingUse(..)) => match borrow.kind() {
+            BorrowKind::Mut { .. } => match borrow.activation_location() {
+                TwoPhaseActivation::NotTwoPhase => true,
+                TwoPhaseActivation::NotActivated => false,
+                TwoPhaseActivation::ActivatedAt(activation) => {
+                    activation.dominates(location, body.basic_blocks.dominators())
+                }
+            },
+            BorrowKind::Shared | BorrowKind::Fake(..) => false,
+        },
+        Some(PlaceContext::NonUse(..)) => false,
+    }
+}
+
+// the function at `fn_path` if this crate has one, or the def paths of every function
+// it could be when it has several, eg. `Counter::new` and `Meter::new` for `new`
+pub(crate) fn find_fn(tcx: TyCtxt, fn_path: &str) -> Result<Option<LocalDefId>, Vec<String>> {
+    let candidates = tcx
+        .hir_body_owners()
+        .filter(|def_id| matches_fn_path(tcx, *def_id, fn_path))
+        .collect::<Vec<_>>();
+    match candidates[..] {
+        [] => Ok(None),
+        [def_id] => Ok(Some(def_id)),
+        _ => Err(candidates.iter().map(|def_id| tcx.def_path_str(def_id.to_def_id())).collect()),
+    }
+}
+
+// every loan created in the function `fn_def_id`, including in its closures
+pub(crate) fn fn_loans(tcx: TyCtxt, fn_def_id: LocalDefId) -> Vec<LoanJson> {
+    let source_map = tcx.sess.source_map();
+    let mut loans = Vec::new();
+    for def_id in tcx.hir_body_owners() {
+        if tcx.typeck_root_def_id(def_id.to_def_id()) != fn_def_id.to_def_id() {
+            continue;
+        }
+        let Some(facts) = body_with_facts(tcx, def_id) else { continue };
+        let body = &facts.body;
+        let regions = loan_regions(facts);
+
+        for (borrow, region) in facts.borrow_set.location_map().values().zip(regions) {
+            // fake borrows only exist to keep match guards honest, and borrows of
+            // temporaries come from desugarings the learner never wrote
+            if let BorrowKind::Fake(..) = borrow.kind() {
+                continue;
+            }
+            if !borrow.borrowed_place().is_source_visible(tcx, body) {
+                continue;
+            }
+            let mut live_lines = region
+                .iter()
+                .map(|loc| user_span(body, body.source_info(*loc).span))
+                .filter(|span| !span.is_dummy())
+                .map(|span| source_map.lookup_char_pos(span.lo()).line)
+                .collect::<Vec<_>>();
+            live_lines.sort();
+            live_lines.dedup();
+
+            loans.push(LoanJson {
+                span: SpanJson::in_body(source_map, body, body.source_info(borrow.reserve_location()).span),
+                borrowed_place: place_name(tcx, body, borrow.borrowed_place()),
+                borrow_kind: borrow_kind(borrow.kind()),
+                mutable: borrow.kind().mutability().is_mut(),
+                live_lines,
+            });
+        }
+    }
+    loans.sort_by_key(|loan| (loan.span.line_start, loan.span.column_start));
+    loans
+}
+
+// whether a body's def path is `fn_path`, or ends with it (eg. `factorial` for `Math::factorial`)
+pub(crate) fn matches_fn_path(tcx: TyCtxt, def_id: LocalDefId, fn_path: &str) -> bool {
+    let def_path = tcx.def_path_str(def_id.to_def_id());
+    def_path == fn_path || def_path.ends_with(&format!("::{}", fn_path))
+}
+
+// every location reachable from each loan's creation before it goes out of scope, i.e. its NLL region
+pub(crate) fn loan_regions<'tcx>(facts: &BodyWithBorrowckFacts<'tcx>) -> IndexVec<BorrowIndex, Vec<Location>> {
+    let body = &facts.body;
+    let out_of_scope = consumers::calculate_borrows_out_of_scope_at_location(
+        body,
+        &facts.region_inference_context,
+        &facts.borrow_set,
+    );
+    let mut kills: HashMap<BorrowIndex, HashSet<Location>> = HashMap::new();
+    for (location, loans) in out_of_scope.iter() {
+        for loan in loans {
+            kills.entry(*loan).or_default().insert(*location);
+        }
+    }
+
+    facts.borrow_set.location_map().keys().enumerate().map(|(idx, reserve)| {
+        let kills = kills.remove(&BorrowIndex::from_usize(idx)).unwrap_or_default();
+        let mut region = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![*reserve];
+        while let Some(location) = stack.pop() {
+            if !visited.insert(location) || (location != *reserve && kills.contains(&location)) {
+                continue;
+            }
+            region.push(location);
+            stack.extend(successors(body, location));
+        }
+        region.sort();
+        region
+    }).collect()
+}
+
+// first and last source line covered by a loan's region
+pub(crate) fn region_lines(source_map: &SourceMap, body: &Body, region: &[Location]) -> (usize, usize) {
+    let lines = region
+        .iter()
+        .map(|loc| user_span(body, body.source_info(*loc).span))
+        .filter(|span| !span.is_dummy())
+        .map(|span| source_map.lookup_char_pos(span.lo()).line)
+        .collect::<Vec<_>>();
+    (
+        lines.iter().copied().min().unwrap_or_default(),
+        lines.iter().copied().max().unwrap_or_default(),
+    )
+}
+
+// the use that keeps a loan alive is the last point of its region,
+// preferring the latest one that comes after the conflicting access
+pub(crate) fn later_use(body: &Body, region: &[Location], access_span: Span) -> Option<Location> {
+    let in_region = region.iter().copied().collect::<HashSet<_>>();
+    let ends = region
+        .iter()
+        .copied()
+        .filter(|loc| successors(body, *loc).iter().all(|succ| !in_region.contains(succ)))
+        .filter(|loc| !body.source_info(*loc).span.is_dummy())
+        .collect::<Vec<_>>();
+
+    let start = |loc: &Location| user_span(body, body.source_info(*loc).span).lo();
+    let access_end = user_span(body, access_span).hi();
+    ends.iter()
+        .copied()
+        .filter(|loc| start(loc) >= access_end)
+        .max_by_key(start)
+        .or_else(|| ends.iter().copied().max_by_key(start))
+}
+
+// spans from macros inside a body are mapped back to the user's code in that body
+pub(crate) fn user_span(body: &Body, span: Span) -> Span {
+    span.find_ancestor_inside(body.span).unwrap_or(span)
+}
+
+pub(crate) fn successors(body: &Body, location: Location) -> Vec<Location> {
+    let block = &body.basic_blocks[location.block];
+    if location.statement_index < block.statements.len() {
+        vec![location.successor_within_block()]
+    } else {
+        block.terminator().successors().map(|bb| bb.start_location()).collect()
+    }
+}
+
+pub(crate) fn place_name<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, place: Place<'tcx>) -> String {
+    place.to_string(tcx, body).unwrap_or_else(|| format!("{:?}", place))
+}
+
+pub(crate) fn borrow_kind(kind: BorrowKind) -> String {
+    match kind {
+        BorrowKind::Shared => "Shared".to_string(),
+        BorrowKind::Fake(..) => "Fake".to_string(),
+        BorrowKind::Mut { kind: MutBorrowKind::Default } => "Mut".to_string(),
+        BorrowKind::Mut { kind: MutBorrowKind::TwoPhaseBorrow } => "TwoPhaseMut".to_string(),
+        BorrowKind::Mut { kind: MutBorrowKind::ClosureCapture } => "UniqueClosureCapture".to_string(),
+    }
+}
+
+// how the statement at `location` touches the borrowed place
+fn access_kind<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, borrowed: Place<'tcx>, location: Location) -> String {
+    match access_context(tcx, body, borrowed, location) {
+        Some(PlaceContext::MutatingUse(MutatingUseContext::Borrow)) => "MutBorrow",
+        Some(PlaceContext::NonMutatingUse(
+            NonMutatingUseContext::SharedBorrow | NonMutatingUseContext::FakeBorrow,
+        )) => "SharedBorrow",
+        Some(PlaceContext::NonMutatingUse(NonMutatingUseContext::Move)) => "Move",
+        Some(PlaceContext::NonMutatingUse(..)) => "Read",
+        Some(PlaceContext::MutatingUse(MutatingUseContext::Drop))
+        | Some(PlaceContext::NonUse(NonUseContext::StorageDead)) => "Drop",
+        Some(PlaceContext::MutatingUse(..)) => "Write",
+        _ => "Unknown",
+    }
+    .to_string()
+}
+
+// the context of the first place at `location` that overlaps the borrowed place
+fn access_context<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, borrowed: Place<'tcx>, location: Location) -> Option<PlaceContext> {
+    let mut finder = AccessFinder { tcx, body, borrowed, context: None };
+    let block = &body.basic_blocks[location.block];
+    match block.statements.get(location.statement_index) {
+        Some(stmt) => finder.visit_statement(stmt, location),
+        None => finder.visit_terminator(block.terminator(), location),
+    }
+    finder.context
+}
+
+struct AccessFinder<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    body: &'a Body<'tcx>,
+    borrowed: Place<'tcx>,
+    context: Option<PlaceContext>,
+}
+
+impl<'tcx> Visitor<'tcx> for AccessFinder<'_, 'tcx> {
+    fn visit_place(&mut self, place: &Place<'tcx>, context: PlaceContext, location: Location) {
+        if self.context.is_none()
+            && !matches!(context, PlaceContext::NonUse(..))
+            && consumers::places_conflict(self.tcx, self.body, self.borrowed, *place, PlaceConflictBias::Overlap)
+        {
+            self.context = Some(context);
+        }
+        self.super_place(place, context, location);
+    }
+
+    fn visit_local(&mut self, local: Local, context: PlaceContext, _location: Location) {
+        if self.context.is_none()
+            && local == self.borrowed.local
+            && context == PlaceContext::NonUse(NonUseContext::StorageDead)
+        {
+            self.context = Some(context);
+        }
+    }
+}
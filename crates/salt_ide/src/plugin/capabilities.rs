@@ -0,0 +1,57 @@
+use clap::{CommandFactory, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::plugin::anonymize::Identifiers;
+use crate::plugin::output::Format;
+use crate::plugin::print_result::{SaltPluginArgs, SCHEMA_VERSION};
+
+// the toolchain the plugin is built against, which the driver needs at runtime
+const TOOLCHAIN: &str = include_str!("../../rust-toolchain.toml");
+
+// what the installed version supports, so the extension can check it before running anything
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct CapabilitiesJson {
+    pub(crate) version: String,
+    pub(crate) toolchain: String,
+    pub(crate) schema_version: u32,
+    pub(crate) analyses: Vec<String>,
+    pub(crate) formats: Vec<String>,
+    pub(crate) identifiers: Vec<String>,
+}
+
+pub fn capabilities() -> CapabilitiesJson {
+    // the style report runs without a subcommand
+    let analyses = std::iter::once("style".to_string())
+        .chain(SaltPluginArgs::command().get_subcommands().map(|command| command.get_name().to_string()))
+        .collect();
+    CapabilitiesJson {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        toolchain: toolchain_channel().to_string(),
+        schema_version: SCHEMA_VERSION,
+        analyses,
+        formats: value_names::<Format>(),
+        identifiers: value_names::<Identifiers>(),
+    }
+}
+
+pub fn print_capabilities() {
+    println!("{}", serde_json::to_string(&capabilities()).unwrap());
+}
+
+// the `channel` of `rust-toolchain.toml`, eg. `nightly-2025-03-03`
+fn toolchain_channel() -> &'static str {
+    TOOLCHAIN
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| key.trim() == "channel")
+        .map_or("", |(_, value)| value.trim().trim_matches('"'))
+}
+
+// the names the CLI accepts for each value
+fn value_names<T: ValueEnum>() -> Vec<String> {
+    T::value_variants()
+        .iter()
+        .filter_map(|value| value.to_possible_value())
+        .map(|value| value.get_name().to_string())
+        .collect()
+}
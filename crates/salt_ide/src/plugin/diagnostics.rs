@@ -0,0 +1,223 @@
+use rustc_errors::annotate_snippet_emitter_writer::AnnotateSnippetEmitter;
+use rustc_errors::emitter::{
+    stderr_destination, DynEmitter, Emitter, HumanEmitter, HumanReadableErrorType, OutputTheme, SilentEmitter,
+};
+use rustc_errors::json::JsonEmitter;
+use rustc_errors::registry::Registry;
+use rustc_errors::translation::Translate;
+use rustc_errors::{DiagInner, FluentBundle, Level, TerminalUrl};
+use rustc_interface::interface::Config;
+use rustc_middle::ty::TyCtxt;
+use rustc_session::config::{ErrorOutputType, Options};
+use rustc_session::filesearch::sysroot_candidates;
+use rustc_span::source_map::SourceMap;
+use rustc_span::Span;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::plugin::borrowck::SpanJson;
+use crate::plugin::anonymize::hash_id;
+
+// an error or warning rustc reported while compiling the crate
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug, Clone)]
+pub struct DiagnosticJson {
+    pub(crate) code: Option<String>,
+    pub(crate) level: String,
+    pub(crate) span: SpanJson,
+    pub(crate) def_id: Option<String>,
+}
+
+// what the emitter keeps of a diagnostic until there's a `TyCtxt` to attribute it with
+#[derive(Debug, Clone)]
+pub(crate) struct CapturedDiagnostic {
+    code: Option<String>,
+    level: &'static str,
+    span: Span,
+}
+
+pub(crate) type Captured = Arc<Mutex<Vec<CapturedDiagnostic>>>;
+
+// replaces the session's emitter with one that records every diagnostic before emitting it
+// as rustc would, so cargo still sees the same errors
+pub(crate) fn capture_diagnostics(config: &mut Config, captured: Captured) {
+    let opts = config.opts.clone();
+    let locale_resources = config.locale_resources.clone();
+    config.psess_created = Some(Box::new(move |psess| {
+        let emitter = default_emitter(&opts, locale_resources, psess.clone_source_map());
+        psess.dcx().set_emitter(Box::new(CapturingEmitter { emitter, captured }));
+    }));
+}
+
+// records every diagnostic without emitting any, for sessions whose output nobody reads
+pub(crate) fn capture_silently(config: &mut Config, captured: Captured) {
+    let opts = config.opts.clone();
+    let locale_resources = config.locale_resources.clone();
+    config.psess_created = Some(Box::new(move |psess| {
+        let fatal_emitter = default_emitter(&opts, locale_resources, psess.clone_source_map());
+        let emitter = Box::new(SilentEmitter { fatal_emitter, fatal_note: None, emit_fatal_diagnostic: false });
+        psess.dcx().set_emitter(Box::new(CapturingEmitter { emitter, captured }));
+    }));
+}
+
+// the emitter `rustc_session::build_session` gives the session, which can't be taken back out of it.
+// Only the codegen backend's messages are missing from the fallback bundle, and cargo check
+// doesn't generate code.
+fn default_emitter(opts: &Options, locale_resources: Vec<&'static str>, source_map: Arc<SourceMap>) -> Box<DynEmitter> {
+    let unstable_opts = &opts.unstable_opts;
+    // the session was built with the same bundle, so loading it can't fail now
+    let bundle = rustc_errors::fluent_bundle(
+        opts.maybe_sysroot.clone(),
+        sysroot_candidates().to_vec(),
+        unstable_opts.translate_lang.clone(),
+        unstable_opts.translate_additional_ftl.as_deref(),
+        unstable_opts.translate_directionality_markers,
+    )
+    .ok()
+    .flatten();
+    let fallback_bundle = rustc_errors::fallback_fluent_bundle(locale_resources, unstable_opts.translate_directionality_markers);
+    let terminal_url = match unstable_opts.terminal_urls {
+        TerminalUrl::Auto => match (env::var("COLORTERM").as_deref(), env::var("TERM").as_deref()) {
+            (Ok("truecolor"), Ok("xterm-256color")) if opts.unstable_features.is_nightly_build() => TerminalUrl::Yes,
+            _ => TerminalUrl::No,
+        },
+        terminal_url => terminal_url,
+    };
+    let source_map = if unstable_opts.link_only { None } else { Some(source_map) };
+    let ignored_directories = unstable_opts.ignore_directory_in_diagnostics_source_blocks.clone();
+
+    match opts.error_format {
+        ErrorOutputType::HumanReadable(kind @ HumanReadableErrorType::AnnotateSnippet, _) => Box::new(
+            AnnotateSnippetEmitter::new(source_map, bundle, fallback_bundle, kind.short(), unstable_opts.macro_backtrace)
+                .ui_testing(unstable_opts.ui_testing),
+        ),
+        ErrorOutputType::HumanReadable(kind, color_config) => Box::new(
+            HumanEmitter::new(stderr_destination(color_config), fallback_bundle)
+                .fluent_bundle(bundle)
+                .sm(source_map)
+                .short_message(kind.short())
+                .teach(unstable_opts.teach)
+                .diagnostic_width(opts.diagnostic_width)
+                .macro_backtrace(unstable_opts.macro_backtrace)
+                .track_diagnostics(unstable_opts.track_diagnostics)
+                .terminal_url(terminal_url)
+                .theme(if let HumanReadableErrorType::Unicode = kind { OutputTheme::Unicode } else { OutputTheme::Ascii })
+                .ignored_directories_in_source_blocks(ignored_directories)
+                .ui_testing(unstable_opts.ui_testing),
+        ),
+        ErrorOutputType::Json { pretty, json_rendered, color_config } => Box::new(
+            JsonEmitter::new(
+                Box::new(std::io::BufWriter::new(std::io::stderr())),
+                source_map,
+                fallback_bundle,
+                pretty,
+                json_rendered,
+                color_config,
+            )
+            .fluent_bundle(bundle)
+            .ui_testing(unstable_opts.ui_testing)
+            .ignored_directories_in_source_blocks(ignored_directories)
+            .diagnostic_width(opts.diagnostic_width)
+            .macro_backtrace(unstable_opts.macro_backtrace)
+            .track_diagnostics(unstable_opts.track_diagnostics)
+            .terminal_url(terminal_url),
+        ),
+    }
+}
+
+// attributes each captured diagnostic to the innermost item containing its primary span
+pub(crate) fn diagnostics_json(tcx: TyCtxt, captured: &Captured) -> Vec<DiagnosticJson> {
+    let source_map = tcx.sess.source_map();
+    let items = tcx
+        .hir_crate_items(())
+        .owners()
+        .map(|owner| (owner.to_def_id(), tcx.hir().span_with_body(tcx.local_def_id_to_hir_id(owner.def_id))))
+        .collect::<Vec<_>>();
+
+    let captured = captured.lock().unwrap();
+    captured
+        .iter()
+        .map(|diagnostic| {
+            let item = items
+                .iter()
+                .filter(|(_, span)| span.contains(diagnostic.span))
+                .min_by_key(|(_, span)| span.hi() - span.lo());
+            DiagnosticJson {
+                code: diagnostic.code.clone(),
+                level: diagnostic.level.to_string(),
+                span: SpanJson::new(source_map, diagnostic.span),
+                def_id: item.map(|(def_id, _)| hash_id(tcx, def_id)),
+            }
+        })
+        .collect()
+}
+
+// the primary spans of the errors captured so far
+pub(crate) fn error_spans(captured: &Captured) -> Vec<Span> {
+    let captured = captured.lock().unwrap();
+    captured.iter().filter(|diagnostic| diagnostic.level == "Error").map(|diagnostic| diagnostic.span).collect()
+}
+
+struct CapturingEmitter {
+    emitter: Box<DynEmitter>,
+    captured: Captured,
+}
+
+impl Emitter for CapturingEmitter {
+    fn emit_diagnostic(&mut self, diag: DiagInner, registry: &Registry) {
+        let level = match diag.level() {
+            Level::Error | Level::Fatal => Some("Error"),
+            Level::Warning | Level::ForceWarning(..) => Some("Warning"),
+            _ => None,
+        };
+        // summaries like "aborting due to 2 previous errors" have no span to attribute
+        if let (Some(level), Some(span)) = (level, diag.span.primary_span()) {
+            self.captured.lock().unwrap().push(CapturedDiagnostic {
+                code: diag.code.map(|code| code.to_string()),
+                level,
+                span,
+            });
+        }
+        self.emitter.emit_diagnostic(diag, registry);
+    }
+
+    fn emit_artifact_notification(&mut self, path: &Path, artifact_type: &str) {
+        self.emitter.emit_artifact_notification(path, artifact_type);
+    }
+
+    fn emit_future_breakage_report(&mut self, diags: Vec<DiagInner>, registry: &Registry) {
+        self.emitter.emit_future_breakage_report(diags, registry);
+    }
+
+    fn emit_unused_externs(
+        &mut self,
+        lint_level: rustc_lint_defs::Level,
+        unused_externs: &[&str],
+    ) {
+        self.emitter.emit_unused_externs(lint_level, unused_externs);
+    }
+
+    fn should_show_explain(&self) -> bool {
+        self.emitter.should_show_explain()
+    }
+
+    fn supports_color(&self) -> bool {
+        self.emitter.supports_color()
+    }
+
+    fn source_map(&self) -> Option<&SourceMap> {
+        self.emitter.source_map()
+    }
+}
+
+impl Translate for CapturingEmitter {
+    fn fluent_bundle(&self) -> Option<&FluentBundle> {
+        self.emitter.fluent_bundle()
+    }
+
+    fn fallback_fluent_bundle(&self) -> &FluentBundle {
+        self.emitter.fallback_fluent_bundle()
+    }
+}
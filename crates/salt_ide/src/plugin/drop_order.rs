@@ -0,0 +1,226 @@
+use rustc_borrowck::consumers::BodyWithBorrowckFacts;
+use rustc_hir::def_id::LocalDefId;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::Stmt;
+use rustc_middle::mir::{Body, Local, LocalInfo, Location, StatementKind, TerminatorKind, VarDebugInfoContents};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::plugin::borrowck::{self, body_with_facts, loan_regions, user_span, SpanJson};
+use crate::plugin::anonymize::hash_id;
+
+// the end of every local's life in a body that has a borrow outliving its local
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct BodyDropsJson {
+    pub(crate) def_id: String,
+    pub(crate) locals: Vec<LocalDropJson>,
+    pub(crate) extended_temporaries: Vec<TemporaryJson>,
+}
+
+// where a local's storage ends and which borrows of it are still needed at that point
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct LocalDropJson {
+    pub(crate) local: String,
+    pub(crate) is_temporary: bool,
+    pub(crate) decl_span: SpanJson,
+    pub(crate) storage_dead: Vec<SpanJson>,
+    pub(crate) drops: Vec<SpanJson>,
+    pub(crate) scope_end_line: usize,
+    pub(crate) outstanding_borrows: Vec<OutstandingBorrowJson>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct OutstandingBorrowJson {
+    pub(crate) borrow_span: SpanJson,
+    pub(crate) dropped_span: SpanJson,
+    pub(crate) needed_span: Option<SpanJson>,
+}
+
+// a temporary that lives until the end of its block instead of the end of its statement,
+// eg. the `String` in `let r = &String::new();`
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct TemporaryJson {
+    pub(crate) expr: String,
+    pub(crate) decl_span: SpanJson,
+    pub(crate) storage_dead: SpanJson,
+}
+
+// bodies with E0597/E0716 errors, or every body of the function at `fn_path`
+pub(crate) fn body_drops(tcx: TyCtxt, fn_path: Option<&str>) -> Vec<BodyDropsJson> {
+    let mut bodies = Vec::new();
+    for def_id in tcx.hir_body_owners() {
+        if let Some(fn_path) = fn_path {
+            let root = tcx.typeck_root_def_id(def_id.to_def_id()).expect_local();
+            if !borrowck::matches_fn_path(tcx, root, fn_path) {
+                continue;
+            }
+        }
+        let Some(facts) = body_with_facts(tcx, def_id) else { continue };
+        let body = drops_in_body(tcx, def_id, facts);
+        if fn_path.is_some() || body.locals.iter().any(|local| !local.outstanding_borrows.is_empty()) {
+            bodies.push(body);
+        }
+    }
+    bodies
+}
+
+fn drops_in_body<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    def_id: LocalDefId,
+    facts: &BodyWithBorrowckFacts<'tcx>,
+) -> BodyDropsJson {
+    let body = &facts.body;
+    let source_map = tcx.sess.source_map();
+    let span_json = |span: Span| SpanJson::in_body(source_map, body, span);
+
+    // where each local's storage ends and where it is dropped, outside of unwinding
+    let mut storage_dead: HashMap<Local, Vec<Location>> = HashMap::new();
+    let mut drops: HashMap<Local, Vec<Location>> = HashMap::new();
+    for (bb, data) in body.basic_blocks.iter_enumerated() {
+        if data.is_cleanup {
+            continue;
+        }
+        for (statement_index, stmt) in data.statements.iter().enumerate() {
+            if let StatementKind::StorageDead(local) = stmt.kind {
+                storage_dead.entry(local).or_default().push(Location { block: bb, statement_index });
+            }
+        }
+        if let TerminatorKind::Drop { place, .. } = &data.terminator().kind {
+            if place.projection.is_empty() {
+                drops.entry(place.local).or_default().push(body.terminator_loc(bb));
+            }
+        }
+    }
+
+    // a loan is outstanding when its local's storage ends or it's dropped inside the loan's region
+    let regions = loan_regions(facts);
+    let mut outstanding: HashMap<Local, Vec<OutstandingBorrowJson>> = HashMap::new();
+    for (borrow, region) in facts.borrow_set.location_map().values().zip(&regions) {
+        let local = borrow.borrowed_place().local;
+        let ends = storage_dead.get(&local).into_iter().chain(drops.get(&local)).flatten();
+        let Some(end) = ends.filter(|loc| region.binary_search(loc).is_ok()).min() else { continue };
+        let dropped_span = body.source_info(*end).span;
+        outstanding.entry(local).or_default().push(OutstandingBorrowJson {
+            borrow_span: span_json(body.source_info(borrow.reserve_location()).span),
+            dropped_span: span_json(dropped_span),
+            needed_span: borrowck::later_use(body, region, dropped_span)
+                .map(|loc| span_json(body.source_info(loc).span)),
+        });
+    }
+
+    // user variables are scoped by their debug info, which covers the rest of their block
+    let mut visibility = HashMap::new();
+    for info in &body.var_debug_info {
+        if let VarDebugInfoContents::Place(place) = info.value {
+            if place.projection.is_empty() {
+                visibility.insert(place.local, info.source_info.scope);
+            }
+        }
+    }
+
+    let stmt_spans = stmt_spans(tcx, def_id);
+    let mut locals = Vec::new();
+    let mut extended_temporaries = Vec::new();
+    for (local, decl) in body.local_decls.iter_enumerated() {
+        let decl_span = user_span(body, decl.source_info.span);
+        let is_temporary = !decl.is_user_variable();
+        let outstanding_borrows = outstanding.remove(&local).unwrap_or_default();
+        if decl_span.is_dummy() || !body.span.contains(decl_span) {
+            continue;
+        }
+        if is_temporary && is_extended(body, local, decl_span, &storage_dead, &stmt_spans) {
+            let end = storage_dead[&local].iter().max().unwrap();
+            extended_temporaries.push(TemporaryJson {
+                expr: source_map.span_to_snippet(decl_span).unwrap_or_default(),
+                decl_span: span_json(decl_span),
+                storage_dead: span_json(body.source_info(*end).span),
+            });
+        }
+        // only temporaries that are borrowed past their end matter for E0716
+        if is_temporary && outstanding_borrows.is_empty() {
+            continue;
+        }
+
+        // temporaries are scoped by the statement creating them
+        let scope_span = match visibility.get(&local) {
+            Some(scope) => user_span(body, body.source_scopes[*scope].span),
+            None => enclosing_stmt(decl_span, &stmt_spans)
+                .unwrap_or_else(|| user_span(body, body.source_scopes[decl.source_info.scope].span)),
+        };
+        let spans = |locs: Option<&Vec<Location>>| {
+            let mut spans = locs
+                .into_iter()
+                .flatten()
+                .map(|loc| span_json(body.source_info(*loc).span))
+                .collect::<Vec<_>>();
+            spans.sort_by_key(|span| (span.line_start, span.column_start));
+            spans.dedup();
+            spans
+        };
+        locals.push(LocalDropJson {
+            local: if is_temporary {
+                source_map.span_to_snippet(decl_span).unwrap_or_default()
+            } else {
+                borrowck::place_name(tcx, body, local.into())
+            },
+            is_temporary,
+            decl_span: span_json(decl_span),
+            storage_dead: spans(storage_dead.get(&local)),
+            drops: spans(drops.get(&local)),
+            scope_end_line: source_map.lookup_char_pos(scope_span.hi()).line,
+            outstanding_borrows,
+        });
+    }
+
+    BodyDropsJson {
+        def_id: hash_id(tcx, &def_id.to_def_id()),
+        locals,
+        extended_temporaries,
+    }
+}
+
+// a temporary is extended when its storage outlives the statement that creates it
+fn is_extended(
+    body: &Body,
+    local: Local,
+    decl_span: Span,
+    storage_dead: &HashMap<Local, Vec<Location>>,
+    stmt_spans: &[Span],
+) -> bool {
+    if matches!(
+        body.local_decls[local].local_info(),
+        LocalInfo::DerefTemp | LocalInfo::FakeBorrow
+    ) {
+        return false;
+    }
+    let Some(stmt) = enclosing_stmt(decl_span, stmt_spans) else { return false };
+    storage_dead.get(&local).is_some_and(|locs| {
+        locs.iter()
+            .any(|loc| user_span(body, body.source_info(*loc).span).lo() >= stmt.hi())
+    })
+}
+
+fn enclosing_stmt(span: Span, stmt_spans: &[Span]) -> Option<Span> {
+    stmt_spans
+        .iter()
+        .copied()
+        .filter(|stmt| stmt.contains(span))
+        .min_by_key(|stmt| stmt.hi() - stmt.lo())
+}
+
+fn stmt_spans(tcx: TyCtxt, def_id: LocalDefId) -> Vec<Span> {
+    struct StmtCollector(Vec<Span>);
+
+    impl<'tcx> Visitor<'tcx> for StmtCollector {
+        fn visit_stmt(&mut self, stmt: &'tcx Stmt<'tcx>) {
+            self.0.push(stmt.span);
+            intravisit::walk_stmt(self, stmt);
+        }
+    }
+
+    let mut collector = StmtCollector(Vec::new());
+    collector.visit_body(tcx.hir_body_owned_by(def_id));
+    collector.0
+}
@@ -0,0 +1,375 @@
+use rustc_hir::def_id::LocalDefId;
+use rustc_middle::ty::TyCtxt;
+use rustc_span::source_map::{FileLoader, RealFileLoader};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{fs, io, thread};
+
+use crate::plugin::borrowck::{self, BorrowErrorJson, SpanJson};
+use crate::plugin::diagnostics::{self, Captured};
+use crate::plugin::move_paths;
+use crate::plugin::anonymize::hash_id;
+
+// the candidate edits for one borrow error that borrowck accepted once applied
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct FixJson {
+    pub(crate) def_id: String,
+    pub(crate) code: String,
+    pub(crate) error_span: SpanJson,
+    pub(crate) candidates: Vec<CandidateJson>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct CandidateJson {
+    pub(crate) kind: String,
+    pub(crate) edits: Vec<EditJson>,
+}
+
+// replaces the text at `span`, an empty span inserts
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct EditJson {
+    pub(crate) span: SpanJson,
+    pub(crate) replacement: String,
+}
+
+// one error in a body with its candidates, before they are verified
+struct ErrorSite {
+    code: &'static str,
+    error_span: SpanJson,
+    candidates: Vec<CandidateJson>,
+}
+
+// synthesizes candidate edits for every borrow error and keeps those that fix it,
+// checking each one with a fresh compiler session run from `compiler_args`
+pub(crate) fn verified_fixes(tcx: TyCtxt, compiler_args: &[String], emitted: &Captured) -> Vec<FixJson> {
+    let compiler_args = verification_args(compiler_args);
+    let mut fixes = Vec::new();
+    for def_id in tcx.hir_body_owners() {
+        let def_path = tcx.def_path_str(def_id.to_def_id());
+        let sites = error_sites(tcx, def_id, emitted);
+        if sites.is_empty() {
+            continue;
+        }
+        let baseline = body_errors(&compiler_args, &def_path, &[]);
+        for site in sites {
+            let target = (Some(site.code.to_string()), site.error_span.line_start);
+            let candidates = site
+                .candidates
+                .into_iter()
+                .filter(|candidate| {
+                    let Some(baseline) = &baseline else { return false };
+                    body_errors(&compiler_args, &def_path, &candidate.edits)
+                        .is_some_and(|errors| fixes_error(baseline, &errors, &target))
+                })
+                .collect::<Vec<_>>();
+            fixes.push(FixJson {
+                def_id: hash_id(tcx, &def_id.to_def_id()),
+                code: site.code.to_string(),
+                error_span: site.error_span,
+                candidates,
+            });
+        }
+    }
+    fixes
+}
+
+fn error_sites(tcx: TyCtxt, def_id: LocalDefId, emitted: &Captured) -> Vec<ErrorSite> {
+    let mut sites = Vec::new();
+    for error in borrowck::body_borrowck_errors(tcx, def_id, emitted) {
+        let Some(source) = read_source(&error.access_span) else { continue };
+        sites.push(ErrorSite {
+            code: borrow_error_code(&error),
+            error_span: error.access_span.clone(),
+            candidates: borrow_candidates(&source, &error),
+        });
+    }
+    for local in move_paths::body_moves(tcx, def_id) {
+        for use_after_move in &local.uses_after_move {
+            let Some(source) = read_source(&use_after_move.span) else { continue };
+            let mut candidates = Vec::new();
+            for moved in local.moves.iter().filter(|m| use_after_move.move_outs.contains(&m.move_out)) {
+                candidates.extend(moved_value_candidates(&source, &moved.span));
+                candidates.extend(reorder_use(&source, &use_after_move.span, moved.span.line_start));
+            }
+            sites.push(ErrorSite {
+                code: "E0382",
+                error_span: use_after_move.span.clone(),
+                candidates: dedup(candidates),
+            });
+        }
+    }
+    sites
+}
+
+// the rustc error code for a loan invalidated by an access
+fn borrow_error_code(error: &BorrowErrorJson) -> &'static str {
+    match (error.borrow_kind.as_str(), error.access_kind.as_str()) {
+        (_, "Move") => "E0505",
+        (_, "Drop") => "E0597",
+        (_, "Write") => "E0506",
+        ("Mut" | "TwoPhaseMut", "MutBorrow") => "E0499",
+        _ => "E0502",
+    }
+}
+
+fn borrow_candidates(source: &str, error: &BorrowErrorJson) -> Vec<CandidateJson> {
+    let mut candidates = Vec::new();
+    if error.access_kind == "Move" {
+        candidates.extend(moved_value_candidates(source, &error.access_span));
+    }
+
+    // `&x` becomes `x.clone()`, so the value no longer borrows from `x`
+    let borrow = snippet(source, &error.borrow_span);
+    if let Some(borrowed) = borrow.strip_prefix("&mut ").or_else(|| borrow.strip_prefix('&')) {
+        candidates.push(CandidateJson {
+            kind: "Clone".to_string(),
+            edits: vec![EditJson {
+                span: error.borrow_span.clone(),
+                replacement: format!("{}.clone()", borrowed.trim()),
+            }],
+        });
+    }
+
+    // ends the borrow before the conflicting access by wrapping its lines in a block
+    if error.borrow_span.line_start < error.access_span.line_start {
+        let first = error.borrow_span.line_start;
+        let last = error.access_span.line_start - 1;
+        let indent = line(source, first).len() - line(source, first).trim_start().len();
+        candidates.push(CandidateJson {
+            kind: "NarrowScope".to_string(),
+            edits: vec![
+                EditJson {
+                    span: point(&error.borrow_span, first, indent + 1),
+                    replacement: "{ ".to_string(),
+                },
+                EditJson {
+                    span: point(&error.borrow_span, last, line(source, last).chars().count() + 1),
+                    replacement: " }".to_string(),
+                },
+            ],
+        });
+    }
+
+    if let Some(later_use) = &error.later_use_span {
+        candidates.extend(reorder_use(source, later_use, error.access_span.line_start));
+    }
+    dedup(candidates)
+}
+
+// clone or borrow a value instead of moving it
+fn moved_value_candidates(source: &str, moved: &SpanJson) -> Vec<CandidateJson> {
+    let value = snippet(source, moved);
+    [
+        ("Clone", format!("{}.clone()", value)),
+        ("Borrow", format!("&{}", value)),
+        ("BorrowMut", format!("&mut {}", value)),
+    ]
+    .into_iter()
+    .map(|(kind, replacement)| CandidateJson {
+        kind: kind.to_string(),
+        edits: vec![EditJson { span: moved.clone(), replacement }],
+    })
+    .collect()
+}
+
+// moves the line of a later use up to just before `before_line`
+fn reorder_use(source: &str, later_use: &SpanJson, before_line: usize) -> Option<CandidateJson> {
+    if later_use.line_start != later_use.line_end || later_use.line_start <= before_line {
+        return None;
+    }
+    let use_line = later_use.line_start;
+    Some(CandidateJson {
+        kind: "ReorderUse".to_string(),
+        edits: vec![
+            EditJson {
+                span: point(later_use, before_line, 1),
+                replacement: format!("{}\n", line(source, use_line)),
+            },
+            EditJson {
+                span: SpanJson { line_end: use_line + 1, column_end: 1, ..point(later_use, use_line, 1) },
+                replacement: String::new(),
+            },
+        ],
+    })
+}
+
+fn dedup(candidates: Vec<CandidateJson>) -> Vec<CandidateJson> {
+    let mut unique: Vec<CandidateJson> = Vec::new();
+    for candidate in candidates {
+        if !unique.iter().any(|other| other.edits == candidate.edits) {
+            unique.push(candidate);
+        }
+    }
+    unique
+}
+
+// an empty span in the file of `span`
+fn point(span: &SpanJson, line: usize, column: usize) -> SpanJson {
+    SpanJson {
+        file_name: span.file_name.clone(),
+        line_start: line,
+        column_start: column,
+        line_end: line,
+        column_end: column,
+    }
+}
+
+fn read_source(span: &SpanJson) -> Option<String> {
+    fs::read_to_string(&span.file_name).ok()
+}
+
+fn line(source: &str, line: usize) -> &str {
+    source.lines().nth(line - 1).unwrap_or_default()
+}
+
+fn snippet<'a>(source: &'a str, span: &SpanJson) -> &'a str {
+    let (lo, hi) = (offset(source, span.line_start, span.column_start), offset(source, span.line_end, span.column_end));
+    &source[lo..hi.max(lo)]
+}
+
+// byte offset of a 1-based line and column, where columns count chars like rustc's
+fn offset(source: &str, line: usize, column: usize) -> usize {
+    let line_start = source
+        .split_inclusive('\n')
+        .take(line - 1)
+        .map(str::len)
+        .sum::<usize>();
+    let rest = &source[line_start.min(source.len())..];
+    line_start + rest.char_indices().nth(column - 1).map_or(rest.len(), |(idx, _)| idx)
+}
+
+fn apply_edits(source: &str, edits: &[EditJson]) -> String {
+    let mut edits = edits
+        .iter()
+        .map(|edit| {
+            let lo = offset(source, edit.span.line_start, edit.span.column_start);
+            let hi = offset(source, edit.span.line_end, edit.span.column_end);
+            (lo, hi, edit.replacement.as_str())
+        })
+        .collect::<Vec<_>>();
+    edits.sort_by_key(|(lo, ..)| std::cmp::Reverse(*lo));
+    let mut edited = source.to_string();
+    for (lo, hi, replacement) in edits {
+        edited.replace_range(lo..hi, replacement);
+    }
+    edited
+}
+
+// the same compiler invocation, without the incremental cache the running session holds
+fn verification_args(compiler_args: &[String]) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut iter = compiler_args.iter().peekable();
+    while let Some(arg) = iter.next() {
+        if arg == "-C" && iter.peek().is_some_and(|next| next.starts_with("incremental=")) {
+            iter.next();
+            continue;
+        }
+        if arg.starts_with("-Cincremental=") {
+            continue;
+        }
+        args.push(arg.clone());
+    }
+    args
+}
+
+// an error rustc reported, by its code and the line of its primary span. None of the
+// candidates adds or removes lines, so the lines of the other errors stay put.
+type ErrorKey = (Option<String>, usize);
+
+// whether the edits removed the targeted error and some error of the baseline,
+// without reporting any error the baseline doesn't have
+fn fixes_error(baseline: &[ErrorKey], errors: &[ErrorKey], target: &ErrorKey) -> bool {
+    let mut remaining = baseline.to_vec();
+    for error in errors {
+        match remaining.iter().position(|other| other == error) {
+            Some(idx) => remaining.remove(idx),
+            None => return false,
+        };
+    }
+    !remaining.is_empty() && !errors.contains(target)
+}
+
+// the errors reported while type-checking and borrow-checking the body at `def_path` and its
+// closures with the edits applied, or `None` if the session never got to the body
+fn body_errors(compiler_args: &[String], def_path: &str, edits: &[EditJson]) -> Option<Vec<ErrorKey>> {
+    let mut files = HashMap::new();
+    if let Some(file_name) = edits.first().map(|edit| &edit.span.file_name) {
+        let source = fs::read_to_string(file_name).ok()?;
+        files.insert(fs::canonicalize(file_name).ok()?, apply_edits(&source, edits));
+    }
+
+    let errors = Arc::new(Mutex::new(None));
+    let mut callbacks = VerifyCallbacks {
+        def_path: def_path.to_string(),
+        files: Some(files),
+        captured: Captured::default(),
+        errors: errors.clone(),
+    };
+    let compiler_args = compiler_args.to_vec();
+    // every compiler session needs its own thread for its session globals, and sessions
+    // with errors abort once the callbacks return, so the errors are read after the join
+    let _ = thread::spawn(move || rustc_driver::run_compiler(&compiler_args, &mut callbacks)).join();
+    errors.lock().unwrap().take()
+}
+
+struct VerifyCallbacks {
+    def_path: String,
+    files: Option<HashMap<PathBuf, String>>,
+    captured: Captured,
+    errors: Arc<Mutex<Option<Vec<ErrorKey>>>>,
+}
+
+impl rustc_driver::Callbacks for VerifyCallbacks {
+    fn config(&mut self, config: &mut rustc_interface::interface::Config) {
+        config.file_loader = Some(Box::new(EditedFiles(self.files.take().unwrap_or_default())));
+        diagnostics::capture_silently(config, self.captured.clone());
+    }
+
+    fn after_expansion(
+        &mut self,
+        _compiler: &rustc_interface::interface::Compiler,
+        tcx: TyCtxt<'_>,
+    ) -> rustc_driver::Compilation {
+        let Some(root) = tcx
+            .hir_body_owners()
+            .find(|def_id| tcx.def_path_str(def_id.to_def_id()) == self.def_path)
+        else {
+            return rustc_driver::Compilation::Stop;
+        };
+        let root = tcx.typeck_root_def_id(root.to_def_id());
+        for def_id in tcx.hir_body_owners().filter(|def_id| tcx.typeck_root_def_id(def_id.to_def_id()) == root) {
+            tcx.ensure_ok().check_unsafety(def_id);
+            tcx.ensure_ok().mir_borrowck(def_id);
+        }
+        let errors = diagnostics::diagnostics_json(tcx, &self.captured)
+            .into_iter()
+            .filter(|diagnostic| diagnostic.level == "Error")
+            .map(|diagnostic| (diagnostic.code, diagnostic.span.line_start))
+            .collect();
+        *self.errors.lock().unwrap() = Some(errors);
+        rustc_driver::Compilation::Stop
+    }
+}
+
+// serves the edited source in place of the files on disk
+struct EditedFiles(HashMap<PathBuf, String>);
+
+impl FileLoader for EditedFiles {
+    fn file_exists(&self, path: &Path) -> bool {
+        RealFileLoader.file_exists(path)
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        let edited = fs::canonicalize(path).ok().and_then(|path| self.0.get(&path).cloned());
+        match edited {
+            Some(source) => Ok(source),
+            None => RealFileLoader.read_file(path),
+        }
+    }
+
+    fn read_binary_file(&self, path: &Path) -> io::Result<Arc<[u8]>> {
+        RealFileLoader.read_binary_file(path)
+    }
+}
@@ -0,0 +1,400 @@
+use rustc_ast::LitKind;
+use rustc_hir::def::Res;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{BinOpKind, Block, Expr, ExprKind, HirId, LangItem, LoopSource, MatchSource, Mutability, Node, OwnerId, Pat, PatKind, QPath, StmtKind, UnOp};
+use rustc_middle::ty::{TyCtxt, TyKind, TypeckResults};
+use rustc_span::def_id::DefId;
+use rustc_span::{sym, Span};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::plugin::anonymize::{hash_id, identifiers};
+use crate::plugin::visit_hir::{for_loop_head, is_range, source_json, ty_impls_iter, SourceJson};
+
+// a loop written the way learners often start out, which has a known iterator form
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug, Clone)]
+pub struct IdiomJson {
+    pub(crate) source: SourceJson,
+    pub(crate) def_id: String,
+    pub(crate) kind: String,
+    // the iterator form, with placeholder names unless identifiers are readable
+    pub(crate) suggestion: String,
+}
+
+pub(crate) struct Idiom {
+    span: Span,
+    // the loop up to its head, where the hint points
+    header: Span,
+    def_id: DefId,
+    suggestion: Suggestion,
+}
+
+// the idiomatic form of a loop, with the learner's names for the hints
+enum Suggestion {
+    // `for i in 0..v.len()` using `v[i]`
+    Iterate { collection: String, counter: String, enumerate: bool, mutable: bool },
+    // pushing into a `Vec` on every iteration, or on those passing an `if`
+    Collect { iter: String, filter: bool, map: bool },
+    // adding one to a counter on every iteration, or on those passing an `if`
+    Count { iter: String, filter: bool },
+    // keeping the smallest or largest element seen so far
+    MinMax { iter: String, max: bool, map: bool },
+    // `while i < n` with `i += 1` in its body
+    Range { counter: String, start: String, end: String, inclusive: bool },
+}
+
+impl Suggestion {
+    fn kind(&self) -> &'static str {
+        match self {
+            Suggestion::Iterate { .. } => "IndexLoop",
+            Suggestion::Collect { .. } => "PushLoop",
+            Suggestion::Count { .. } => "CountLoop",
+            Suggestion::MinMax { .. } => "MinMaxLoop",
+            Suggestion::Range { .. } => "WhileIndex",
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self {
+            Suggestion::Iterate { .. } => "this loop indexes a collection with its counter",
+            Suggestion::Collect { .. } => "this loop builds a `Vec` one element at a time",
+            Suggestion::Count { .. } => "this loop counts elements one at a time",
+            Suggestion::MinMax { max: false, .. } => "this loop searches for the smallest element by hand",
+            Suggestion::MinMax { max: true, .. } => "this loop searches for the largest element by hand",
+            Suggestion::Range { .. } => "this `while` loop drives an index by hand",
+        }
+    }
+
+    // the iterator form, with the learner's names or with placeholders
+    fn form(&self, names: bool) -> String {
+        let name = |real: &str, placeholder: &str| if names { real.to_string() } else { placeholder.to_string() };
+        let filter = |filter: bool| if filter { ".filter(..)" } else { "" };
+        let map = |map: bool| if map { ".map(..)" } else { "" };
+        match self {
+            Suggestion::Iterate { collection, counter, enumerate: true, mutable } => {
+                let iter = if *mutable { "iter_mut" } else { "iter" };
+                format!("for ({}, item) in {}.{}().enumerate()", name(counter, "i"), name(collection, "v"), iter)
+            }
+            Suggestion::Iterate { collection, enumerate: false, mutable, .. } => {
+                format!("for item in &{}{}", if *mutable { "mut " } else { "" }, name(collection, "v"))
+            }
+            Suggestion::Collect { iter, filter: f, map: m } => format!("{}{}{}.collect()", name(iter, "iter"), filter(*f), map(*m)),
+            Suggestion::Count { iter, filter: f } => format!("{}{}.count()", name(iter, "iter"), filter(*f)),
+            Suggestion::MinMax { iter, max, map: m } => {
+                format!("{}{}.{}()", name(iter, "iter"), map(*m), if *max { "max" } else { "min" })
+            }
+            Suggestion::Range { counter, start, end, inclusive } => {
+                let range = if *inclusive { "..=" } else { ".." };
+                format!("for {} in {}{}{}", name(counter, "i"), name(start, "start"), range, name(end, "end"))
+            }
+        }
+    }
+}
+
+impl Idiom {
+    pub(crate) fn to_json(&self, tcx: TyCtxt) -> IdiomJson {
+        IdiomJson {
+            source: source_json(tcx, &self.def_id, self.span),
+            def_id: hash_id(tcx, &self.def_id),
+            kind: self.suggestion.kind().to_string(),
+            suggestion: self.suggestion.form(identifiers().readable()),
+        }
+    }
+}
+
+// the idioms in every body of the owner, its closures included
+pub(crate) fn owner_idioms(tcx: TyCtxt, owner: OwnerId) -> Vec<Idiom> {
+    let mut idioms = Vec::new();
+    for (_, body) in tcx.hir_owner_nodes(owner).bodies.iter() {
+        if !tcx.has_typeck_results(tcx.hir_body_owner_def_id(body.id())) {
+            continue;
+        }
+        let typeck_results = tcx.typeck_body(body.id());
+        if typeck_results.tainted_by_errors.is_some() {
+            continue;
+        }
+        let mut visitor = IdiomVisitor { tcx, typeck_results, idioms: Vec::new() };
+        visitor.visit_body(body);
+        idioms.extend(visitor.idioms);
+    }
+    idioms
+}
+
+// warns about every idiom in the crate, for the learner to see next to rustc's own warnings
+pub(crate) fn emit_hints(tcx: TyCtxt) {
+    for owner in tcx.hir_crate_items(()).owners() {
+        for idiom in owner_idioms(tcx, owner) {
+            tcx.dcx()
+                .struct_span_warn(idiom.header, idiom.suggestion.message())
+                .with_help(format!("consider `{}`", idiom.suggestion.form(true)))
+                .emit();
+        }
+    }
+}
+
+// visits one body, leaving closures to their own bodies
+struct IdiomVisitor<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    typeck_results: &'tcx TypeckResults<'tcx>,
+    idioms: Vec<Idiom>,
+}
+
+impl<'tcx> Visitor<'tcx> for IdiomVisitor<'tcx> {
+    fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+        let (suggestion, header) = match expr.kind {
+            ExprKind::Loop(block, _, LoopSource::ForLoop, header) => (self.for_loop(expr, block), header),
+            ExprKind::Loop(block, _, LoopSource::While, header) => (self.while_loop(block), header),
+            _ => (None, expr.span),
+        };
+        if let Some(suggestion) = suggestion {
+            self.idioms.push(Idiom { span: expr.span, header, def_id: expr.hir_id.owner.to_def_id(), suggestion });
+        }
+        intravisit::walk_expr(self, expr);
+    }
+}
+
+impl<'tcx> IdiomVisitor<'tcx> {
+    fn for_loop(&self, loop_expr: &'tcx Expr<'tcx>, block: &'tcx Block<'tcx>) -> Option<Suggestion> {
+        let head = for_loop_head(self.tcx, loop_expr)?;
+        let (pat, body) = for_loop_body(block)?;
+        if let Some(suggestion) = self.index_loop(head, pat, body) {
+            return Some(suggestion);
+        }
+        let iter = self.iterator_form(head);
+        let body = only_expr(body)?;
+        match body.kind {
+            ExprKind::If(cond, then, None) => {
+                let then = only_expr(then)?;
+                self.min_max(cond, then, pat, &iter).or_else(|| self.accumulate(then, pat, iter, true))
+            }
+            _ => self.accumulate(body, pat, iter, false),
+        }
+    }
+
+    // `for i in 0..v.len()` with `v[i]` in its body
+    fn index_loop(&self, head: &'tcx Expr<'tcx>, pat: &'tcx Pat<'tcx>, body: &'tcx Expr<'tcx>) -> Option<Suggestion> {
+        let ExprKind::Struct(QPath::LangItem(LangItem::Range, _), [start, end], _) = head.kind else { return None };
+        let ExprKind::MethodCall(segment, collection, [], _) = end.expr.kind else { return None };
+        if segment.ident.as_str() != "len" || !is_int(start.expr, 0) {
+            return None;
+        }
+        let mut uses = CounterUses { collection: local(collection)?, counter: binding(pat)?, indexing: 0, other: 0, mutable: false };
+        uses.visit_expr(body);
+        (uses.indexing > 0).then(|| Suggestion::Iterate {
+            collection: self.snippet(collection.span),
+            counter: self.snippet(pat.span),
+            enumerate: uses.other > 0,
+            mutable: uses.mutable,
+        })
+    }
+
+    // pushing into a `Vec` or adding one to a counter
+    fn accumulate(&self, expr: &'tcx Expr<'tcx>, pat: &'tcx Pat<'tcx>, iter: String, filter: bool) -> Option<Suggestion> {
+        match expr.kind {
+            ExprKind::MethodCall(segment, vec, [element], _)
+                if segment.ident.as_str() == "push" && local(vec).is_some() && self.is_vec(vec) =>
+            {
+                Some(Suggestion::Collect { iter, filter, map: !is_binding(element, pat) })
+            }
+            ExprKind::AssignOp(op, counter, one) if op.node == BinOpKind::Add && local(counter).is_some() && is_int(one, 1) => {
+                Some(Suggestion::Count { iter, filter })
+            }
+            _ => None,
+        }
+    }
+
+    // `if x < min { min = x }`, where `min` is on the larger side of the comparison
+    // and is assigned the element it was compared with
+    fn min_max(&self, cond: &'tcx Expr<'tcx>, assign: &'tcx Expr<'tcx>, pat: &'tcx Pat<'tcx>, iter: &str) -> Option<Suggestion> {
+        let ExprKind::Binary(op, lhs, rhs) = condition(cond).kind else { return None };
+        let ExprKind::Assign(target, value, _) = assign.kind else { return None };
+        let best = local(target);
+        let (max, element) = match op.node {
+            BinOpKind::Lt | BinOpKind::Le if local(rhs) == best => (false, lhs),
+            BinOpKind::Gt | BinOpKind::Ge if local(lhs) == best => (false, rhs),
+            BinOpKind::Gt | BinOpKind::Ge if local(rhs) == best => (true, lhs),
+            BinOpKind::Lt | BinOpKind::Le if local(lhs) == best => (true, rhs),
+            _ => return None,
+        };
+        if best.is_none() || !same_expr(element, value) {
+            return None;
+        }
+        Some(Suggestion::MinMax { iter: iter.to_string(), max, map: !is_binding(value, pat) })
+    }
+
+    // `while i < n { ..; i += 1 }`, lowered to `loop { if i < n { .. } else { break } }`
+    fn while_loop(&self, block: &'tcx Block<'tcx>) -> Option<Suggestion> {
+        let Some(Expr { kind: ExprKind::If(cond, body, _), .. }) = block.expr else { return None };
+        let ExprKind::Binary(op, counter, end) = condition(cond).kind else { return None };
+        if !matches!(op.node, BinOpKind::Lt | BinOpKind::Le | BinOpKind::Ne) {
+            return None;
+        }
+        let mut steps = CounterSteps { counter: local(counter)?, steps: 0 };
+        steps.visit_expr(body);
+        if steps.steps == 0 {
+            return None;
+        }
+        // the counter's initial value, if it is a local declared with one
+        let start = match self.tcx.parent_hir_node(steps.counter) {
+            Node::LetStmt(let_stmt) => let_stmt.init.map(|init| self.snippet(init.span)),
+            _ => None,
+        };
+        Some(Suggestion::Range {
+            counter: self.snippet(counter.span),
+            start: start.unwrap_or_else(|| self.snippet(counter.span)),
+            end: self.snippet(end.span),
+            inclusive: op.node == BinOpKind::Le,
+        })
+    }
+
+    // the head of a for loop as an iterator, eg. `v.iter()` for `&v`
+    fn iterator_form(&self, head: &'tcx Expr<'tcx>) -> String {
+        let ty = self.typeck_results.expr_ty(head);
+        match head.kind {
+            ExprKind::AddrOf(_, Mutability::Not, inner) => format!("{}.iter()", self.snippet(inner.span)),
+            ExprKind::AddrOf(_, Mutability::Mut, inner) => format!("{}.iter_mut()", self.snippet(inner.span)),
+            _ if is_range(self.tcx, ty) => format!("({})", self.snippet(head.span)),
+            _ if ty_impls_iter(self.tcx, ty, head) => self.snippet(head.span),
+            _ => format!("{}.into_iter()", self.snippet(head.span)),
+        }
+    }
+
+    fn is_vec(&self, expr: &Expr) -> bool {
+        match self.typeck_results.expr_ty(expr).peel_refs().kind() {
+            TyKind::Adt(adt, _) => self.tcx.is_diagnostic_item(sym::Vec, adt.did()),
+            _ => false,
+        }
+    }
+
+    fn snippet(&self, span: Span) -> String {
+        self.tcx.sess.source_map().span_to_snippet(span).unwrap_or_else(|_| "..".to_string())
+    }
+}
+
+// how an index loop uses its counter: to index the collection, or for anything else
+struct CounterUses {
+    collection: HirId,
+    counter: HirId,
+    indexing: usize,
+    other: usize,
+    // whether an indexed element is assigned to
+    mutable: bool,
+}
+
+impl<'tcx> Visitor<'tcx> for CounterUses {
+    fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+        match expr.kind {
+            ExprKind::Assign(place, ..) | ExprKind::AssignOp(_, place, _) if self.indexes(place) => self.mutable = true,
+            ExprKind::Index(..) if self.indexes(expr) => {
+                self.indexing += 1;
+                return;
+            }
+            _ if local(expr) == Some(self.counter) => self.other += 1,
+            _ => {}
+        }
+        intravisit::walk_expr(self, expr);
+    }
+}
+
+impl CounterUses {
+    fn indexes(&self, expr: &Expr) -> bool {
+        matches!(expr.kind, ExprKind::Index(base, index, _)
+            if local(base) == Some(self.collection) && local(index) == Some(self.counter))
+    }
+}
+
+// the `+= 1`s of a counter
+struct CounterSteps {
+    counter: HirId,
+    steps: usize,
+}
+
+impl<'tcx> Visitor<'tcx> for CounterSteps {
+    fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+        if let ExprKind::AssignOp(op, counter, one) = expr.kind {
+            if op.node == BinOpKind::Add && local(counter) == Some(self.counter) && is_int(one, 1) {
+                self.steps += 1;
+            }
+        }
+        intravisit::walk_expr(self, expr);
+    }
+}
+
+// the pattern and body of a for loop, lowered to
+// `loop { match Iterator::next(&mut iter) { None => break, Some(pat) => body } }`
+fn for_loop_body<'tcx>(block: &'tcx Block<'tcx>) -> Option<(&'tcx Pat<'tcx>, &'tcx Expr<'tcx>)> {
+    let [stmt] = block.stmts else { return None };
+    let StmtKind::Expr(next) = stmt.kind else { return None };
+    let ExprKind::Match(_, [_, some], MatchSource::ForLoopDesugar) = next.kind else { return None };
+    let PatKind::Struct(_, [field], _) = some.pat.kind else { return None };
+    Some((field.pat, some.body))
+}
+
+// the only expression of a block like `{ v.push(x); }`, or the expression itself
+fn only_expr<'tcx>(expr: &'tcx Expr<'tcx>) -> Option<&'tcx Expr<'tcx>> {
+    let ExprKind::Block(block, None) = expr.kind else { return Some(expr) };
+    match (block.stmts, block.expr) {
+        ([], Some(expr)) => only_expr(expr),
+        ([stmt], None) => match stmt.kind {
+            StmtKind::Expr(expr) | StmtKind::Semi(expr) => only_expr(expr),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// conditions without `let`s are lowered inside a `DropTemps`
+fn condition<'tcx>(cond: &'tcx Expr<'tcx>) -> &'tcx Expr<'tcx> {
+    match cond.kind {
+        ExprKind::DropTemps(inner) => inner,
+        _ => cond,
+    }
+}
+
+fn local(expr: &Expr) -> Option<HirId> {
+    match expr.kind {
+        ExprKind::Path(QPath::Resolved(None, path)) => match path.res {
+            Res::Local(hir_id) => Some(hir_id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn binding(pat: &Pat) -> Option<HirId> {
+    match pat.kind {
+        PatKind::Binding(_, hir_id, _, None) => Some(hir_id),
+        _ => None,
+    }
+}
+
+// whether expr is the loop's element itself, eg. `x` or `*x` for `for x in &v`
+fn is_binding(expr: &Expr, pat: &Pat) -> bool {
+    let expr = match expr.kind {
+        ExprKind::Unary(UnOp::Deref, inner) => inner,
+        _ => expr,
+    };
+    binding(pat).is_some_and(|binding| local(expr) == Some(binding))
+}
+
+// whether two side-effect free expressions read the same value, eg. `*x` and `*x` or `p.len()` and `p.len()`
+fn same_expr(a: &Expr, b: &Expr) -> bool {
+    match (a.kind, b.kind) {
+        (ExprKind::Path(QPath::Resolved(None, a)), ExprKind::Path(QPath::Resolved(None, b))) => a.res == b.res,
+        (ExprKind::Lit(a), ExprKind::Lit(b)) => a.node == b.node,
+        (ExprKind::Unary(a_op, a), ExprKind::Unary(b_op, b)) => a_op == b_op && same_expr(a, b),
+        (ExprKind::Field(a, a_field), ExprKind::Field(b, b_field)) => a_field.name == b_field.name && same_expr(a, b),
+        (ExprKind::Index(a, a_idx, _), ExprKind::Index(b, b_idx, _)) => same_expr(a, b) && same_expr(a_idx, b_idx),
+        (ExprKind::MethodCall(a_method, a, a_args, _), ExprKind::MethodCall(b_method, b, b_args, _)) => {
+            a_method.ident.name == b_method.ident.name
+                && same_expr(a, b)
+                && a_args.len() == b_args.len()
+                && a_args.iter().zip(b_args).all(|(a, b)| same_expr(a, b))
+        }
+        _ => false,
+    }
+}
+
+fn is_int(expr: &Expr, value: u128) -> bool {
+    matches!(expr.kind, ExprKind::Lit(lit) if matches!(lit.node, LitKind::Int(n, _) if n.get() == value))
+}
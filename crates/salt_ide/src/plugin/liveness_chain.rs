@@ -0,0 +1,197 @@
+use rustc_borrowck::consumers::{BodyWithBorrowckFacts, BorrowIndex, PoloniusRegionVid};
+use rustc_hir::def_id::LocalDefId;
+use rustc_middle::mir::{Body, Local, Location};
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use rustc_utils::PlaceExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::plugin::borrowck::{self, body_with_facts, loan_regions, SpanJson};
+use crate::plugin::diagnostics::Captured;
+use crate::plugin::ownership_query::Position;
+use crate::plugin::anonymize::hash_id;
+
+// why a loan is still live at the point it's needed: the steps its loan flows through
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct LivenessJson {
+    pub(crate) def_id: String,
+    pub(crate) borrowed_place: String,
+    pub(crate) borrow_span: SpanJson,
+    pub(crate) conflict_span: Option<SpanJson>,
+    pub(crate) steps: Vec<LivenessStepJson>,
+}
+
+// one link of the chain, in order from the borrow to the use that keeps it alive
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct LivenessStepJson {
+    pub(crate) kind: String,
+    pub(crate) variable: Option<String>,
+    pub(crate) span: SpanJson,
+}
+
+// explains the loan of every borrow error reported to `emitted`, or the loan borrowed at `position`
+pub(crate) fn liveness_chains(tcx: TyCtxt, position: Option<&Position>, emitted: &Captured) -> Vec<LivenessJson> {
+    let source_map = tcx.sess.source_map();
+    let mut chains = Vec::new();
+    for def_id in tcx.hir_body_owners() {
+        let Some(facts) = body_with_facts(tcx, def_id) else { continue };
+        let body = &facts.body;
+        let regions = loan_regions(facts);
+
+        let loans = match position {
+            Some(position) => facts
+                .borrow_set
+                .location_map()
+                .values()
+                .enumerate()
+                .filter(|(_, borrow)| {
+                    let span = body.source_info(borrow.reserve_location()).span;
+                    borrow.borrowed_place().is_source_visible(tcx, body)
+                        && position.is_inside(&SpanJson::in_body(source_map, body, span))
+                })
+                .map(|(idx, _)| (BorrowIndex::from_usize(idx), None))
+                .collect::<Vec<_>>(),
+            None => borrowck::error_loans(tcx, facts, &regions, emitted)
+                .into_iter()
+                .map(|(loan, location)| (loan, Some(location)))
+                .collect(),
+        };
+
+        for (loan, conflict) in loans {
+            let region = &regions[loan];
+            let borrow = &facts.borrow_set[loan];
+            // without a conflict, the loan is explained up to the end of its region
+            let after = conflict.unwrap_or(borrow.reserve_location());
+            let target = borrowck::later_use(body, region, body.source_info(after).span);
+            chains.push(LivenessJson {
+                def_id: hash_id(tcx, &def_id.to_def_id()),
+                borrowed_place: borrowck::place_name(tcx, body, borrow.borrowed_place()),
+                borrow_span: SpanJson::in_body(source_map, body, body.source_info(borrow.reserve_location()).span),
+                conflict_span: conflict
+                    .map(|location| SpanJson::in_body(source_map, body, body.source_info(location).span)),
+                steps: chain_steps(tcx, def_id, facts, loan, region, target),
+            });
+        }
+    }
+    chains
+}
+
+fn chain_steps<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    def_id: LocalDefId,
+    facts: &BodyWithBorrowckFacts<'tcx>,
+    loan: BorrowIndex,
+    region: &[Location],
+    target: Option<Location>,
+) -> Vec<LivenessStepJson> {
+    let body = &facts.body;
+    let source_map = tcx.sess.source_map();
+    let step = |kind: &str, variable: Option<String>, span: Span| LivenessStepJson {
+        kind: kind.to_string(),
+        variable,
+        span: SpanJson::in_body(source_map, body, span),
+    };
+    let borrow = &facts.borrow_set[loan];
+    let mut steps = vec![step(
+        "Borrow",
+        Some(borrowck::place_name(tcx, body, borrow.borrowed_place())),
+        body.source_info(borrow.reserve_location()).span,
+    )];
+    let (Some(input), Some(location_table)) = (&facts.input_facts, &facts.location_table) else {
+        return steps;
+    };
+
+    // the variables whose types carry each origin, user variables first
+    let mut carriers: HashMap<PoloniusRegionVid, Vec<Local>> = HashMap::new();
+    for (local, origin) in &input.use_of_var_derefs_origin {
+        carriers.entry(*origin).or_default().push(*local);
+    }
+    for locals in carriers.values_mut() {
+        locals.sort_by_key(|local| (!body.local_decls[*local].is_user_variable(), *local));
+    }
+    let user_variable = |origin: PoloniusRegionVid| {
+        carriers
+            .get(&origin)
+            .and_then(|locals| locals.first())
+            .filter(|local| body.local_decls[**local].is_user_variable())
+            .map(|local| borrowck::place_name(tcx, body, (*local).into()))
+    };
+
+    // a variable whose origin holds the loan keeps it alive where the variable is used or dropped
+    let mut ends: HashMap<PoloniusRegionVid, (&str, Local)> = HashMap::new();
+    if let Some(target) = target {
+        let at_target = |point| location_table.to_location(point) == target;
+        let used = input.var_used_at.iter().filter(|(_, point)| at_target(*point)).map(|(local, _)| ("Use", *local));
+        let dropped = input.var_dropped_at.iter().filter(|(_, point)| at_target(*point)).map(|(local, _)| ("Drop", *local));
+        for (kind, local) in used.chain(dropped) {
+            let origins = if kind == "Use" { &input.use_of_var_derefs_origin } else { &input.drop_of_var_derefs_origin };
+            for (_, origin) in origins.iter().filter(|(var, _)| *var == local) {
+                ends.entry(*origin).or_insert((kind, local));
+            }
+        }
+    }
+    let universal = input.universal_region.iter().copied().collect::<HashSet<_>>();
+
+    // breadth-first through the outlives constraints that hold while the loan is live,
+    // so the chain is the shortest way the loan reaches its end
+    let starts = input.loan_issued_at.iter().filter(|(_, issued, _)| *issued == loan).map(|(origin, _, _)| *origin);
+    let mut parents: HashMap<PoloniusRegionVid, (PoloniusRegionVid, Location)> = HashMap::new();
+    let mut queue = starts.collect::<VecDeque<_>>();
+    let mut visited = queue.iter().copied().collect::<HashSet<_>>();
+    let (mut used, mut outlives) = (None, None);
+    while let Some(origin) = queue.pop_front() {
+        // reaching the signature explains more than any use inside the body
+        if universal.contains(&origin) {
+            outlives = Some(origin);
+            break;
+        }
+        if ends.contains_key(&origin) {
+            used = used.or(Some(origin));
+        }
+        for (sup, sub, point) in &input.subset_base {
+            let location = location_table.to_location(*point);
+            if *sup != origin || visited.contains(sub) || region.binary_search(&location).is_err() {
+                continue;
+            }
+            visited.insert(*sub);
+            parents.insert(*sub, (origin, location));
+            queue.push_back(*sub);
+        }
+    }
+    let Some(end) = outlives.or(used) else { return steps };
+
+    let mut flows = Vec::new();
+    let mut origin = end;
+    while let Some((parent, location)) = parents.get(&origin) {
+        flows.push((user_variable(origin), *location));
+        origin = *parent;
+    }
+    for (variable, location) in flows.into_iter().rev() {
+        // flows between desugaring temporaries don't mean anything to the learner
+        if variable.is_some() {
+            steps.push(step("FlowsInto", variable, body.source_info(location).span));
+        }
+    }
+
+    match ends.get(&end).filter(|_| outlives.is_none()) {
+        Some((kind, local)) => {
+            // uses in macros go through temporaries, which stand for the last variable the loan reached
+            let variable = if body.local_decls[*local].is_user_variable() {
+                Some(borrowck::place_name(tcx, body, (*local).into()))
+            } else {
+                steps.iter().rev().find(|step| step.kind == "FlowsInto").and_then(|step| step.variable.clone())
+            };
+            steps.push(step(kind, variable, body.source_info(target.unwrap()).span));
+        }
+        // an origin from the signature outlives the whole body, eg. a returned reference
+        None => steps.push(step("OutlivesFunction", None, fn_sig_span(tcx, def_id, body))),
+    }
+    steps
+}
+
+fn fn_sig_span(tcx: TyCtxt, def_id: LocalDefId, body: &Body) -> Span {
+    tcx.hir_fn_sig_by_hir_id(tcx.local_def_id_to_hir_id(def_id))
+        .map(|sig| sig.span)
+        .unwrap_or(body.span)
+}
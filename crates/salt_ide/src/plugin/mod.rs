@@ -1,3 +1,17 @@
+pub mod anonymize;
+pub mod borrowck;
+pub mod capabilities;
+pub mod diagnostics;
+pub mod drop_order;
+pub mod fix_candidates;
+pub mod idioms;
+pub mod liveness_chain;
+pub mod move_paths;
+pub mod output;
+pub mod owner_cache;
+pub mod ownership_query;
 pub mod print_result;
+pub mod server;
+pub mod type_mismatch;
 pub mod visit_hir;
 pub mod tests;
\ No newline at end of file
@@ -0,0 +1,258 @@
+use rustc_hir::def_id::LocalDefId;
+use rustc_middle::mir::visit::{MutatingUseContext, NonMutatingUseContext, PlaceContext, Visitor};
+use rustc_middle::mir::{
+    AggregateKind, Body, Local, Location, Operand, Place, Rvalue, StatementKind, TerminatorKind,
+    RETURN_PLACE,
+};
+use rustc_middle::ty::TyCtxt;
+use rustc_mir_dataflow::move_paths::{
+    InitLocation, LookupResult, MoveData, MoveOutIndex, MovePathIndex,
+};
+use rustc_span::DesugaringKind;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::plugin::borrowck::{body_with_facts, place_name, user_span, SpanJson};
+use crate::plugin::anonymize::hash_id;
+
+// a user variable that is moved at least once, with every use borrowck sees after a move
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct MovedLocalJson {
+    pub(crate) def_id: String,
+    pub(crate) local: String,
+    pub(crate) ty: String,
+    pub(crate) is_copy: bool,
+    pub(crate) moves: Vec<MoveJson>,
+    pub(crate) uses_after_move: Vec<UseAfterMoveJson>,
+}
+
+// one `MoveOutIndex` of the body's move data
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct MoveJson {
+    pub(crate) move_out: usize,
+    pub(crate) place: String,
+    pub(crate) kind: String,
+    pub(crate) span: SpanJson,
+}
+
+// a use of a place that may be (partially) uninitialized by the moves in `move_outs`
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct UseAfterMoveJson {
+    pub(crate) place: String,
+    pub(crate) span: SpanJson,
+    pub(crate) move_outs: Vec<usize>,
+}
+
+// collects the moves of user variables in every body of the crate
+pub(crate) fn moved_locals(tcx: TyCtxt) -> Vec<MovedLocalJson> {
+    let mut locals = Vec::new();
+    for def_id in tcx.hir_body_owners() {
+        locals.extend(body_moves(tcx, def_id));
+    }
+    locals
+}
+
+pub(crate) fn body_moves(tcx: TyCtxt, def_id: LocalDefId) -> Vec<MovedLocalJson> {
+    let Some(facts) = body_with_facts(tcx, def_id) else { return Vec::new() };
+    let body = &facts.body;
+    let source_map = tcx.sess.source_map();
+    let move_data = MoveData::gather_moves(body, tcx, |_| true);
+    let typing_env = body.typing_env(tcx);
+
+    // moves are grouped by the user variable they come out of
+    let mut moves: BTreeMap<Local, Vec<MoveJson>> = BTreeMap::new();
+    for (idx, move_out) in move_data.moves.iter_enumerated() {
+        let Some(kind) = move_kind(body, &move_data, idx) else { continue };
+        let local = move_data.base_local(move_out.path);
+        if !body.local_decls[local].is_user_variable() {
+            continue;
+        }
+        moves.entry(local).or_default().push(MoveJson {
+            move_out: idx.index(),
+            place: place_name(tcx, body, move_data.move_paths[move_out.path].place),
+            kind: kind.to_string(),
+            span: SpanJson::in_body(source_map, body, body.source_info(move_out.source).span),
+        });
+    }
+
+    let mut finder = UseFinder { body, move_data: &move_data, uses: Vec::new() };
+    finder.visit_body(body);
+    let mut seen = HashSet::new();
+    let mut uses: BTreeMap<Local, Vec<UseAfterMoveJson>> = BTreeMap::new();
+    for (place, lookup, location) in finder.uses {
+        let move_outs = reaching_moves(body, &move_data, lookup, location);
+        if move_outs.is_empty() {
+            continue;
+        }
+        let span = user_span(body, body.source_info(location).span);
+        if !seen.insert((place.local, span)) {
+            continue;
+        }
+        uses.entry(place.local).or_default().push(UseAfterMoveJson {
+            place: place_name(tcx, body, place),
+            span: SpanJson::new(source_map, span),
+            move_outs: move_outs.iter().map(|idx| idx.index()).collect(),
+        });
+    }
+
+    moves
+        .into_iter()
+        .map(|(local, moves)| {
+            let ty = body.local_decls[local].ty;
+            MovedLocalJson {
+                def_id: hash_id(tcx, &def_id.to_def_id()),
+                local: place_name(tcx, body, Place::from(local)),
+                ty: ty.to_string(),
+                is_copy: tcx.type_is_copy_modulo_regions(typing_env, ty),
+                moves,
+                uses_after_move: uses.remove(&local).unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+// how a move is consumed, or None for moves that only end a temporary's storage
+fn move_kind(body: &Body, move_data: &MoveData, idx: MoveOutIndex) -> Option<&'static str> {
+    let location = move_data.moves[idx].source;
+    let block = &body.basic_blocks[location.block];
+    match block.statements.get(location.statement_index) {
+        Some(stmt) => match &stmt.kind {
+            StatementKind::StorageDead(..) => None,
+            StatementKind::Assign(assign) => match &assign.1 {
+                Rvalue::Aggregate(kind, _) if matches!(**kind, AggregateKind::Closure(..)) => {
+                    Some("ClosureCapture")
+                }
+                _ if assign.0.local == RETURN_PLACE => Some("Return"),
+                // `foo(x)` first moves `x` into a temporary, so the temporary's use decides
+                Rvalue::Use(Operand::Move(_)) if assign.0.as_local().is_some_and(|tmp| {
+                    !body.local_decls[tmp].is_user_variable()
+                }) =>
+                {
+                    move_data
+                        .rev_lookup
+                        .find_local(assign.0.local)
+                        .and_then(|tmp| {
+                            move_data.path_map[tmp]
+                                .iter()
+                                .find_map(|tmp_move| move_kind(body, move_data, *tmp_move))
+                        })
+                        .or(Some("Assignment"))
+                }
+                _ => Some("Assignment"),
+            },
+            _ => Some("Other"),
+        },
+        None => match &block.terminator().kind {
+            TerminatorKind::Call { fn_span, .. }
+                if fn_span.is_desugaring(DesugaringKind::ForLoop)
+                    || block.terminator().source_info.span.is_desugaring(DesugaringKind::ForLoop) =>
+            {
+                Some("ForLoop")
+            }
+            TerminatorKind::Call { .. } | TerminatorKind::TailCall { .. } => Some("CallArgument"),
+            _ => Some("Other"),
+        },
+    }
+}
+
+// walks backwards from a use to every move of an overlapping path that isn't reinitialized in between,
+// the same question borrowck answers with its maybe-uninitialized dataflow
+pub(crate) fn reaching_moves(
+    body: &Body,
+    move_data: &MoveData,
+    lookup: LookupResult,
+    use_location: Location,
+) -> Vec<MoveOutIndex> {
+    // a place without its own move path only overlaps the moves of its parents,
+    // eg. using `p.b` is fine after moving `p.a`
+    let (mpi, exact) = match lookup {
+        LookupResult::Exact(mpi) => (mpi, true),
+        LookupResult::Parent(Some(mpi)) => (mpi, false),
+        LookupResult::Parent(None) => return Vec::new(),
+    };
+    let ancestors = std::iter::once(mpi)
+        .chain(move_data.move_paths[mpi].parents(&move_data.move_paths).map(|(parent, _)| parent))
+        .collect::<HashSet<_>>();
+    let is_related = |path: MovePathIndex| {
+        ancestors.contains(&path)
+            || exact && move_data.move_paths[path]
+                .parents(&move_data.move_paths)
+                .any(|(parent, _)| parent == mpi)
+    };
+
+    let mut reaching = Vec::new();
+    let mut visited = HashSet::new();
+    let mut stack = predecessors(body, use_location);
+    while let Some(location) = stack.pop() {
+        if !visited.insert(location) {
+            continue;
+        }
+        let moved = move_data.loc_map[location]
+            .iter()
+            .filter(|idx| is_related(move_data.moves[**idx].path))
+            .filter(|idx| move_kind(body, move_data, **idx).is_some())
+            .copied()
+            .collect::<Vec<_>>();
+        if !moved.is_empty() {
+            reaching.extend(moved);
+            continue;
+        }
+        let initialized = move_data.init_loc_map[location].iter().any(|init| {
+            let init = &move_data.inits[*init];
+            matches!(init.location, InitLocation::Statement(_)) && ancestors.contains(&init.path)
+        });
+        if !initialized {
+            stack.extend(predecessors(body, location));
+        }
+    }
+    reaching.sort();
+    reaching.dedup();
+    reaching
+}
+
+fn predecessors(body: &Body, location: Location) -> Vec<Location> {
+    if location.statement_index > 0 {
+        return vec![Location { block: location.block, statement_index: location.statement_index - 1 }];
+    }
+    body.basic_blocks.predecessors()[location.block]
+        .iter()
+        .filter(|pred| !body.basic_blocks[**pred].is_cleanup)
+        .map(|pred| body.terminator_loc(*pred))
+        .collect()
+}
+
+// every place of a user variable that the body reads, borrows or writes through
+struct UseFinder<'a, 'tcx> {
+    body: &'a Body<'tcx>,
+    move_data: &'a MoveData<'tcx>,
+    uses: Vec<(Place<'tcx>, LookupResult, Location)>,
+}
+
+impl<'tcx> Visitor<'tcx> for UseFinder<'_, 'tcx> {
+    fn visit_place(&mut self, place: &Place<'tcx>, context: PlaceContext, location: Location) {
+        let is_use = match context {
+            PlaceContext::NonMutatingUse(NonMutatingUseContext::FakeBorrow)
+            | PlaceContext::NonMutatingUse(NonMutatingUseContext::PlaceMention) => false,
+            PlaceContext::NonMutatingUse(..) => true,
+            // writing to the whole place reinitializes it, only writes into part of it are uses
+            PlaceContext::MutatingUse(MutatingUseContext::Store | MutatingUseContext::Call) => {
+                !place.projection.is_empty()
+            }
+            PlaceContext::MutatingUse(MutatingUseContext::Drop) => false,
+            PlaceContext::MutatingUse(..) => true,
+            PlaceContext::NonUse(..) => false,
+        };
+        if !is_use
+            || self.body.basic_blocks[location.block].is_cleanup
+            || !self.body.local_decls[place.local].is_user_variable()
+            || user_span(self.body, self.body.source_info(location).span).is_dummy()
+        {
+            return;
+        }
+        let lookup = self.move_data.rev_lookup.find(place.as_ref());
+        if !matches!(lookup, LookupResult::Parent(None)) {
+            self.uses.push((*place, lookup, location));
+        }
+    }
+}
+
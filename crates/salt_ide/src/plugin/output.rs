@@ -0,0 +1,130 @@
+use clap::ValueEnum;
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_middle::ty::TyCtxt;
+use rustc_session::config::CrateType;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::plugin::anonymize::crate_id;
+
+// how records are written
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// One JSON array of every crate's record in the output file, or one record per line on stdout
+    Json,
+    /// One compact record per line
+    #[default]
+    Jsonl,
+    /// Indented records, for reading
+    Pretty,
+}
+
+// which crate a record is about, at the top level of every record
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug, Clone)]
+pub struct CrateJson {
+    pub(crate) crate_id: String,
+    pub(crate) crate_type: String,
+    pub(crate) workspace_member: bool,
+    pub(crate) target_kind: String,
+}
+
+pub(crate) fn crate_json(tcx: TyCtxt) -> CrateJson {
+    CrateJson {
+        crate_id: crate_id(tcx),
+        crate_type: tcx.crate_types().first().map_or("lib".to_string(), CrateType::to_string),
+        // cargo only sets this for the packages it was asked to check, not their dependencies
+        workspace_member: std::env::var_os("CARGO_PRIMARY_PACKAGE").is_some(),
+        target_kind: target_kind(tcx).to_string(),
+    }
+}
+
+// cargo's name for the kind of target being compiled, from what rustc was told about it
+fn target_kind(tcx: TyCtxt) -> &'static str {
+    let crate_types = tcx.crate_types();
+    if tcx.sess.opts.test {
+        "test"
+    } else if tcx.crate_name(LOCAL_CRATE).as_str().starts_with("build_script_") {
+        "custom-build"
+    } else if crate_types.contains(&CrateType::ProcMacro) {
+        "proc-macro"
+    } else if crate_types.contains(&CrateType::Executable) {
+        let in_examples = tcx.sess.io.input.opt_path()
+            .is_some_and(|path| path.components().any(|component| component.as_os_str() == "examples"));
+        if in_examples { "example" } else { "bin" }
+    } else {
+        "lib"
+    }
+}
+
+// where records go: stdout, or a file every crate of the run writes to
+#[derive(Clone, Debug)]
+pub(crate) struct Output {
+    path: Option<PathBuf>,
+    format: Format,
+}
+
+impl Output {
+    pub(crate) fn new(path: Option<PathBuf>, format: Format) -> Self {
+        Output { path, format }
+    }
+
+    pub(crate) fn emit(&self, record: &impl Serialize) {
+        let written = match &self.path {
+            Some(path) => self.write(path, record),
+            None => self.print(record),
+        };
+        if let Err(e) = written {
+            eprintln!("Failed to write results: {}", e);
+        }
+    }
+
+    fn print(&self, record: &impl Serialize) -> io::Result<()> {
+        let json = match self.format {
+            Format::Json | Format::Jsonl => serde_json::to_string(record)?,
+            Format::Pretty => serde_json::to_string_pretty(record)?,
+        };
+        println!("{}", json);
+        Ok(())
+    }
+
+    // cargo compiles crates in parallel, so each driver holds the lock while it adds its record
+    fn write(&self, path: &Path, record: &impl Serialize) -> io::Result<()> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        file.lock()?;
+        match self.format {
+            Format::Json => {
+                let mut contents = String::new();
+                file.read_to_string(&mut contents)?;
+                let mut records: Vec<serde_json::Value> = if contents.trim().is_empty() {
+                    Vec::new()
+                } else {
+                    serde_json::from_str(&contents)?
+                };
+                records.push(serde_json::to_value(record)?);
+                file.set_len(0)?;
+                file.seek(SeekFrom::Start(0))?;
+                writeln!(file, "{}", serde_json::to_string(&records)?)?;
+            }
+            Format::Jsonl => {
+                file.seek(SeekFrom::End(0))?;
+                writeln!(file, "{}", serde_json::to_string(record)?)?;
+            }
+            Format::Pretty => {
+                file.seek(SeekFrom::End(0))?;
+                writeln!(file, "{}", serde_json::to_string_pretty(record)?)?;
+            }
+        }
+        file.unlock()
+    }
+}
+
+// the drivers only ever add to the output file, so the CLI starts each run with an empty one,
+// at an absolute path since each driver runs in its own crate's directory
+pub(crate) fn prepare_output(path: &Path) -> io::Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    File::create(&path)?;
+    Ok(path)
+}
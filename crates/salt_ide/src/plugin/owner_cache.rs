@@ -0,0 +1,156 @@
+use rustc_data_structures::fingerprint::Fingerprint;
+use rustc_data_structures::stable_hasher::{HashStable, StableHasher};
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_hir::OwnerId;
+use rustc_middle::ty::TyCtxt;
+use rustc_query_system::ich::StableHashingContext;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::plugin::anonymize::{hash_string, identifiers};
+use crate::plugin::print_result::SCHEMA_VERSION;
+use crate::plugin::visit_hir::OwnerJson;
+
+// how many owners of the crate came from the cache, reported with the style report
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug, Clone, Default)]
+pub struct CacheJson {
+    pub(crate) reused: usize,
+    pub(crate) recomputed: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: String,
+    owners: HashMap<String, CacheEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    fingerprint: String,
+    result: OwnerJson,
+}
+
+// the style results of each owner from the crate's last run, one file per crate under `target/salt/cache`
+pub(crate) struct OwnerCache {
+    path: Option<PathBuf>,
+    version: String,
+    cached: HashMap<String, CacheEntry>,
+    // what this run found, written back so owners that no longer exist are dropped
+    entries: HashMap<String, CacheEntry>,
+    stats: CacheJson,
+}
+
+impl OwnerCache {
+    // without a directory nothing is read or written, and every owner is recomputed
+    pub(crate) fn load(tcx: TyCtxt, dir: Option<&Path>) -> Self {
+        let version = format!("{}/{}", cache_version(), environment(tcx).to_hex());
+        // the stable crate id tells apart the lib, bins and tests of a package
+        let path = dir.map(|dir| {
+            dir.join(format!("{}-{:016x}.json", tcx.crate_name(LOCAL_CRATE), tcx.stable_crate_id(LOCAL_CRATE).as_u64()))
+        });
+        let cached = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| serde_json::from_str::<CacheFile>(&contents).ok())
+            .filter(|file| file.version == version)
+            .map(|file| file.owners)
+            .unwrap_or_default();
+        OwnerCache { path, version, cached, entries: HashMap::new(), stats: CacheJson::default() }
+    }
+
+    // the owner's cached results if its fingerprint is unchanged, otherwise those of `visit`
+    pub(crate) fn owner(&mut self, tcx: TyCtxt, owner: OwnerId, visit: impl FnOnce() -> OwnerJson) -> OwnerJson {
+        let Some(fingerprint) = fingerprint(tcx, owner) else {
+            self.stats.recomputed += 1;
+            return visit();
+        };
+        let key = format!("{:016x}", tcx.def_path_hash(owner.to_def_id()).local_hash().as_u64());
+        let result = match self.cached.remove(&key) {
+            Some(entry) if entry.fingerprint == fingerprint => {
+                self.stats.reused += 1;
+                entry.result
+            }
+            _ => {
+                self.stats.recomputed += 1;
+                visit()
+            }
+        };
+        self.entries.insert(key, CacheEntry { fingerprint, result: result.clone() });
+        result
+    }
+
+    pub(crate) fn save(self) -> CacheJson {
+        if let Some(path) = &self.path {
+            let file = CacheFile { version: self.version, owners: self.entries };
+            if let Err(e) = write(path, &file) {
+                eprintln!("Failed to write the cache {}: {}", path.display(), e);
+            }
+        }
+        self.stats
+    }
+}
+
+// results only carry over with the same compiler, report shape, identifiers and participant key
+fn cache_version() -> String {
+    format!(
+        "{}/{}/{:?}/{}",
+        rustc_interface::util::rustc_version_str().unwrap_or("unknown"),
+        SCHEMA_VERSION,
+        identifiers(),
+        hash_string("")
+    )
+}
+
+// what the results of every owner depend on besides the owner itself: the crate's type
+// definitions (eg. an enum's variants), its trait impls (eg. whether a type is an `Iterator`)
+// and the crates it uses. Any change to these starts the crate's cache over.
+fn environment(tcx: TyCtxt) -> Fingerprint {
+    tcx.with_stable_hashing_context(|mut hcx| {
+        let mut hasher = StableHasher::new();
+        for def_id in tcx.hir_crate_items(()).definitions() {
+            match tcx.def_kind(def_id) {
+                DefKind::Struct | DefKind::Enum | DefKind::Union => tcx.adt_def(def_id).hash_stable(&mut hcx, &mut hasher),
+                DefKind::Impl { of_trait: true } => tcx.impl_trait_header(def_id).hash_stable(&mut hcx, &mut hasher),
+                _ => {}
+            }
+        }
+        for krate in tcx.crates(()) {
+            tcx.crate_hash(*krate).hash_stable(&mut hcx, &mut hasher);
+        }
+        hasher.finish()
+    })
+}
+
+// The owner's HIR hash (which covers its bodies), where it is, and the types of its bodies,
+// which change with the items they use, eg. the return type of a function they call. These
+// include whether it type-checked. rustc only hashes the HIR when the crate is compiled
+// incrementally or for metadata.
+fn fingerprint(tcx: TyCtxt, owner: OwnerId) -> Option<String> {
+    let hir_hash = tcx.hir_owner_nodes(owner).opt_hash_including_bodies?;
+    let span = tcx.sess.source_map().span_to_diagnostic_string(tcx.source_span(owner.def_id));
+    let types = if tcx.has_typeck_results(owner.def_id) { stable_hash(tcx, tcx.typeck(owner.def_id)) } else { Fingerprint::ZERO };
+    Some(format!("{}:{}:{}", hir_hash.to_hex(), span, types.to_hex()))
+}
+
+fn stable_hash<T: for<'a> HashStable<StableHashingContext<'a>>>(tcx: TyCtxt, value: &T) -> Fingerprint {
+    tcx.with_stable_hashing_context(|mut hcx| {
+        let mut hasher = StableHasher::new();
+        value.hash_stable(&mut hcx, &mut hasher);
+        hasher.finish()
+    })
+}
+
+// the crate may be compiled again while its cache is read, so the file is replaced in one step
+fn write(path: &Path, file: &CacheFile) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension(format!("json.{}", std::process::id()));
+    fs::write(&temp, serde_json::to_string(file)?)?;
+    fs::rename(temp, path)
+}
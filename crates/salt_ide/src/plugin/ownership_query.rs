@@ -0,0 +1,219 @@
+use rustc_borrowck::consumers::BodyWithBorrowckFacts;
+use rustc_middle::mir::{Body, BorrowKind, Location, SourceScope, VarDebugInfoContents};
+use rustc_middle::ty::TyCtxt;
+use rustc_mir_dataflow::impls::{MaybeInitializedPlaces, MaybeUninitializedPlaces};
+use rustc_mir_dataflow::move_paths::{LookupResult, MoveData, MovePathIndex};
+use rustc_mir_dataflow::Analysis;
+use rustc_span::source_map::SourceMap;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::plugin::borrowck::{self, body_with_facts, loan_regions, SpanJson};
+use crate::plugin::move_paths::reaching_moves;
+use crate::plugin::anonymize::hash_id;
+
+// what borrowck knows about the locals in scope at one MIR location
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct OwnershipJson {
+    pub(crate) def_id: String,
+    pub(crate) location: SpanJson,
+    pub(crate) locals: Vec<LocalStateJson>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct LocalStateJson {
+    pub(crate) local: String,
+    pub(crate) ty: String,
+    pub(crate) state: String,
+    pub(crate) loans: Vec<CoveringLoanJson>,
+}
+
+// a loan of the local, or of part of it, that is live at the queried location
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct CoveringLoanJson {
+    pub(crate) span: SpanJson,
+    pub(crate) borrowed_place: String,
+    pub(crate) borrow_kind: String,
+    pub(crate) mutable: bool,
+}
+
+// a position in `file.rs:LINE:COL` form, with 1-based line and column
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub(crate) struct Position {
+    file: String,
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    pub(crate) fn parse(at: &str) -> Option<Self> {
+        let mut parts = at.rsplitn(3, ':');
+        let column = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let file = parts.next()?.to_string();
+        Some(Position { file, line, column })
+    }
+
+    // by whole path components, so `main.rs` doesn't match `domain.rs`. The span's file name is
+    // relative to where rustc runs, so a longer (eg. absolute) path given for the same file matches too.
+    fn in_file(&self, file_name: &str) -> bool {
+        Path::new(file_name).ends_with(&self.file) || Path::new(&self.file).ends_with(file_name)
+    }
+
+    pub(crate) fn is_inside(&self, span: &SpanJson) -> bool {
+        self.in_file(&span.file_name)
+            && (span.line_start, span.column_start) <= (self.line, self.column)
+            && (self.line, self.column) < (span.line_end, span.column_end)
+    }
+}
+
+// the ownership state at `position`, or None if no body in this crate contains it
+pub(crate) fn ownership_at(tcx: TyCtxt, position: &Position) -> Option<OwnershipJson> {
+    let source_map = tcx.sess.source_map();
+
+    // closures are bodies of their own, nested inside their parent's
+    let def_id = tcx
+        .hir_body_owners()
+        .filter_map(|def_id| {
+            let span = SpanJson::new(source_map, tcx.hir_body_owned_by(def_id).value.span);
+            position.is_inside(&span).then_some((def_id, span))
+        })
+        .min_by_key(|(_, span)| extent(span))?
+        .0;
+    let facts = body_with_facts(tcx, def_id)?;
+    let location = innermost_location(source_map, &facts.body, position)?;
+    Some(OwnershipJson {
+        def_id: hash_id(tcx, &def_id.to_def_id()),
+        location: SpanJson::in_body(source_map, &facts.body, facts.body.source_info(location).span),
+        locals: local_states(tcx, facts, location),
+    })
+}
+
+// the location whose span is the smallest one containing the position
+fn innermost_location(source_map: &SourceMap, body: &Body, position: &Position) -> Option<Location> {
+    let mut innermost: Option<(Location, (usize, usize))> = None;
+    for (block, data) in body.basic_blocks.iter_enumerated() {
+        if data.is_cleanup {
+            continue;
+        }
+        for statement_index in 0..=data.statements.len() {
+            let location = Location { block, statement_index };
+            let span = SpanJson::in_body(source_map, body, body.source_info(location).span);
+            if !position.is_inside(&span) {
+                continue;
+            }
+            // ties go to the earliest location, before the statement's effects
+            if innermost.is_none_or(|(_, size)| extent(&span) < size) {
+                innermost = Some((location, extent(&span)));
+            }
+        }
+    }
+    innermost.map(|(location, _)| location)
+}
+
+fn local_states<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    facts: &BodyWithBorrowckFacts<'tcx>,
+    location: Location,
+) -> Vec<LocalStateJson> {
+    let body = &facts.body;
+    let source_map = tcx.sess.source_map();
+    let move_data = MoveData::gather_moves(body, tcx, |_| true);
+
+    let mut maybe_init = MaybeInitializedPlaces::new(tcx, body, &move_data)
+        .iterate_to_fixpoint(tcx, body, None)
+        .into_results_cursor(body);
+    maybe_init.seek_before_primary_effect(location);
+    let mut maybe_uninit = MaybeUninitializedPlaces::new(tcx, body, &move_data)
+        .iterate_to_fixpoint(tcx, body, None)
+        .into_results_cursor(body);
+    maybe_uninit.seek_before_primary_effect(location);
+
+    let regions = loan_regions(facts);
+    let scope = body.source_info(location).scope;
+    let mut locals = Vec::new();
+    for info in &body.var_debug_info {
+        let VarDebugInfoContents::Place(place) = info.value else { continue };
+        if !place.projection.is_empty() || !is_visible(body, info.source_info.scope, scope) {
+            continue;
+        }
+        let local = place.local;
+        let state = match move_data.rev_lookup.find_local(local) {
+            Some(mpi) => {
+                let children = descendants(&move_data, mpi);
+                if !maybe_uninit.get().contains(mpi) {
+                    if children.iter().any(|child| maybe_uninit.get().contains(*child)) {
+                        "PartiallyMoved"
+                    } else {
+                        "Initialized"
+                    }
+                } else if maybe_init.get().contains(mpi) {
+                    "MaybeMoved"
+                } else if reaching_moves(body, &move_data, LookupResult::Exact(mpi), location).is_empty() {
+                    "Uninitialized"
+                } else {
+                    "Moved"
+                }
+            }
+            None => "Initialized",
+        };
+
+        let loans = facts
+            .borrow_set
+            .location_map()
+            .values()
+            .zip(&regions)
+            .filter(|(borrow, region)| {
+                borrow.borrowed_place().local == local
+                    && !matches!(borrow.kind(), BorrowKind::Fake(..))
+                    && region.binary_search(&location).is_ok()
+            })
+            .map(|(borrow, _)| CoveringLoanJson {
+                span: SpanJson::in_body(source_map, body, body.source_info(borrow.reserve_location()).span),
+                borrowed_place: borrowck::place_name(tcx, body, borrow.borrowed_place()),
+                borrow_kind: borrowck::borrow_kind(borrow.kind()),
+                mutable: borrow.kind().mutability().is_mut(),
+            })
+            .collect();
+
+        locals.push(LocalStateJson {
+            local: info.name.to_string(),
+            ty: body.local_decls[local].ty.to_string(),
+            state: state.to_string(),
+            loans,
+        });
+    }
+    locals
+}
+
+// a variable is visible in its own scope and every scope nested inside it
+fn is_visible(body: &Body, var_scope: SourceScope, mut scope: SourceScope) -> bool {
+    loop {
+        if scope == var_scope {
+            return true;
+        }
+        match body.source_scopes[scope].parent_scope {
+            Some(parent) => scope = parent,
+            None => return false,
+        }
+    }
+}
+
+fn descendants(move_data: &MoveData, mpi: MovePathIndex) -> Vec<MovePathIndex> {
+    let mut descendants = Vec::new();
+    let mut stack = vec![mpi];
+    while let Some(path) = stack.pop() {
+        let mut child = move_data.move_paths[path].first_child;
+        while let Some(idx) = child {
+            descendants.push(idx);
+            stack.push(idx);
+            child = move_data.move_paths[idx].next_sibling;
+        }
+    }
+    descendants
+}
+
+// how many lines, then columns, a span covers
+fn extent(span: &SpanJson) -> (usize, usize) {
+    (span.line_end - span.line_start, span.column_end.abs_diff(span.column_start))
+}
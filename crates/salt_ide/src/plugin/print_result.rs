@@ -1,14 +1,27 @@
 //! sample print-all-items plugin from rustc_plugin examples
 
-use std::{borrow::Cow, env, process::Command};
-use clap::Parser;
+use std::{borrow::Cow, env, process::{Command, Stdio}};
+use clap::{Parser, Subcommand};
 use rustc_middle::ty::TyCtxt;
 use rustc_plugin::{CrateFilter, RustcPlugin, RustcPluginArgs, Utf8Path};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
+use crate::plugin::anonymize::{self, Identifiers};
 use crate::plugin::visit_hir::*;
+use crate::plugin::borrowck::{self, BorrowErrorJson, LoanJson};
+use crate::plugin::diagnostics::{self, Captured, DiagnosticJson};
+use crate::plugin::drop_order::{self, BodyDropsJson};
+use crate::plugin::fix_candidates::{self, FixJson};
+use crate::plugin::idioms;
+use crate::plugin::liveness_chain::{self, LivenessJson};
+use crate::plugin::move_paths::{self, MovedLocalJson};
+use crate::plugin::output::{self, crate_json, CrateJson, Format, Output};
+use crate::plugin::owner_cache::{CacheJson, OwnerCache};
+use crate::plugin::ownership_query::{self, OwnershipJson};
+use crate::plugin::type_mismatch::{self, MismatchJson};
 
 // This struct is the plugin provided to the rustc_plugin framework,
 // and it must be exported for use by the CLI/driver binaries.
@@ -16,15 +29,111 @@ pub struct SaltPlugin;
 
 // To parse CLI arguments, we use Clap for this example. But that
 // detail is up to you.
-#[derive(Parser, Serialize, Deserialize)]
+#[derive(Parser, Serialize, Deserialize, Clone)]
 pub struct SaltPluginArgs {
   #[arg(short, long)]
   allcaps: bool,
 
+  /// Print the JSON Schema of the style report and exit, without running cargo
+  #[arg(long)]
+  print_schema: bool,
+
+  /// Print the version, toolchain, analyses and formats this install supports as JSON and exit
+  #[arg(long)]
+  capabilities: bool,
+
+  /// Identify items and files by hash, by readable paths, or both, eg. to audit what is collected
+  #[arg(long, value_enum, default_value_t)]
+  identifiers: Identifiers,
+
+  /// Write records to this file instead of stdout
+  #[arg(long)]
+  output: Option<PathBuf>,
+
+  /// How records are written
+  #[arg(long, value_enum, default_value_t)]
+  format: Format,
+
+  /// Only analyze the workspace's own packages, not their dependencies
+  #[arg(long, group = "crates")]
+  workspace: bool,
+
+  /// Only analyze the package with this name
+  #[arg(short, long, group = "crates")]
+  package: Option<String>,
+
+  /// Only analyze the crate containing this file, eg. `src/main.rs`
+  #[arg(long, group = "crates")]
+  file: Option<PathBuf>,
+
+  /// Analyze every item again instead of reusing the results cached under `target/salt/cache`
+  #[arg(long)]
+  no_cache: bool,
+
+  /// Also warn about loops with a known iterator form, as hints for the learner
+  #[arg(long)]
+  hints: bool,
+
+  // where the style report's per-owner cache is kept, chosen by the CLI from cargo's target directory
+  #[arg(skip)]
+  pub(crate) cache_dir: Option<PathBuf>,
+
+  #[command(subcommand)]
+  command: Option<SaltCommand>,
+
   #[clap(last = true)]
   cargo_args: Vec<String>,
 }
 
+// Analyses that replace the default style report.
+#[derive(Subcommand, Serialize, Deserialize, Clone)]
+pub enum SaltCommand {
+  /// Report the borrowck facts behind every borrow error in the crate
+  Borrowck,
+  /// Report the NLL region of every borrow in a function
+  Borrows {
+    /// Path of the function, eg. `main` or `Math::factorial`
+    #[arg(long = "fn")]
+    fn_path: String,
+  },
+  /// Report where every moved variable is moved and used after the move
+  Moves,
+  /// Report when each local is dropped in bodies with borrows outliving their local
+  Drops {
+    /// Report every body of this function instead, eg. `main` or `Math::factorial`
+    #[arg(long = "fn")]
+    fn_path: Option<String>,
+  },
+  /// Suggest edits for every borrow error, keeping only those that compile
+  Fixes,
+  /// Explain every mismatched types error (E0308) from the types at the mismatch
+  Mismatches,
+  /// Report the ownership state of every local in scope at a source position
+  Query {
+    /// Position as `file.rs:LINE:COL`, eg. `src/main.rs:12:5`
+    #[arg(long = "at")]
+    at: String,
+  },
+  /// Explain which uses and outlives constraints keep each conflicting loan alive
+  Liveness {
+    /// Explain the loan borrowed at this position instead, as `file.rs:LINE:COL`
+    #[arg(long = "at")]
+    at: Option<String>,
+  },
+}
+
+impl SaltCommand {
+  // whether the command reads the facts kept by `borrowck::override_queries`
+  fn needs_borrowck_facts(&self) -> bool {
+    matches!(
+      self,
+      SaltCommand::Borrowck | SaltCommand::Borrows { .. } | SaltCommand::Moves
+        | SaltCommand::Drops { .. } | SaltCommand::Fixes | SaltCommand::Query { .. }
+        | SaltCommand::Liveness { .. }
+    )
+  }
+}
+
 impl RustcPlugin for SaltPlugin {
   type Args = SaltPluginArgs;
 
@@ -37,11 +146,37 @@ impl RustcPlugin for SaltPlugin {
   }
 
   // In the CLI, we ask Clap to parse arguments and also specify a CrateFilter.
-  // If one of the CLI arguments was a specific file to analyze, then you
-  // could provide a different filter.
-  fn args(&self, _target_dir: &Utf8Path) -> RustcPluginArgs<Self::Args> {
-    let args = SaltPluginArgs::parse_from(env::args().skip(1));
-    let filter = CrateFilter::AllCrates;
+  // Every crate is still compiled, the filter only decides which ones are analyzed.
+  fn args(&self, target_dir: &Utf8Path) -> RustcPluginArgs<Self::Args> {
+    let mut args = SaltPluginArgs::parse_from(env::args().skip(1));
+    // the plugin's target directory is a subdirectory of cargo's
+    if !args.no_cache {
+      args.cache_dir = target_dir.parent().map(|dir| dir.as_std_path().join("salt").join("cache"));
+    }
+    if let Some(path) = &args.output {
+      match output::prepare_output(path) {
+        Ok(path) => args.output = Some(path),
+        Err(e) => {
+          eprintln!("Failed to create {}: {}", path.display(), e);
+          std::process::exit(1);
+        }
+      }
+    }
+    let filter = match (&args.file, args.workspace) {
+      (Some(file), _) if !file.exists() => {
+        eprintln!("No such file: {}", file.display());
+        std::process::exit(1);
+      }
+      (Some(file), _) => CrateFilter::CrateContainingFile(file.clone()),
+      // otherwise a misspelled package would compile every crate without analyzing any
+      (None, false) if args.package.as_ref().is_some_and(|package| !package_exists(package)) => {
+        eprintln!("No such package: {}", args.package.as_ref().unwrap());
+        std::process::exit(1);
+      }
+      (None, true) => CrateFilter::OnlyWorkspace,
+      // the package may be a dependency, so it's picked out in the driver
+      (None, false) => CrateFilter::AllCrates,
+    };
     RustcPluginArgs { args, filter }
   }
 
@@ -57,19 +192,86 @@ impl RustcPlugin for SaltPlugin {
     compiler_args: Vec<String>,
     plugin_args: Self::Args,
   ) -> rustc_interface::interface::Result<()> {
-    let mut callbacks = SaltCallbacks {
-      args: Some(plugin_args),
-    };
+    // other packages are compiled as usual, so the package's dependencies still get their metadata
+    if plugin_args.package.as_ref().is_some_and(|package| env::var("CARGO_PKG_NAME").ok().as_ref() != Some(package)) {
+      rustc_driver::run_compiler(&compiler_args, &mut NoAnalysis);
+      return Ok(());
+    }
+    anonymize::set_identifiers(plugin_args.identifiers);
+    let output = Output::new(plugin_args.output.clone(), plugin_args.format);
+    let mut callbacks = SaltCallbacks::new(plugin_args, &compiler_args, output);
     rustc_driver::run_compiler(&compiler_args, &mut callbacks);
     Ok(())
   }
 }
 
-struct SaltCallbacks {
-  args: Option<SaltPluginArgs>,
+// whether the workspace or its dependencies have a package named `package`. If cargo can't
+// read the workspace, it will say why when the crates are compiled.
+fn package_exists(package: &str) -> bool {
+  let Ok(metadata) = Command::new("cargo").args(["metadata", "--format-version", "1"]).stderr(Stdio::null()).output() else {
+    return true;
+  };
+  let Ok(metadata) = serde_json::from_slice::<serde_json::Value>(&metadata.stdout) else { return true };
+  metadata["packages"].as_array().into_iter().flatten().any(|candidate| candidate["name"] == package)
+}
+
+// compiles a crate that wasn't selected for analysis
+pub(crate) struct NoAnalysis;
+
+impl rustc_driver::Callbacks for NoAnalysis {}
+
+pub(crate) struct SaltCallbacks {
+  args: SaltPluginArgs,
+  // fix candidates are checked by compiling the crate again with the same arguments
+  compiler_args: Vec<String>,
+  // errors and warnings rustc emits, reported with the style report and
+  // matched against the loans the borrowck reports explain
+  diagnostics: Captured,
+  output: Output,
 }
 
 impl rustc_driver::Callbacks for SaltCallbacks {
+  // Borrowck facts are only kept around if we override the mir_borrowck query
+  // before the compiler session starts.
+  fn config(&mut self, config: &mut rustc_interface::interface::Config) {
+    match self.command() {
+      // the liveness chains follow the outlives constraints only Polonius' input facts have
+      Some(SaltCommand::Liveness { .. }) => config.override_queries = Some(borrowck::override_queries_with_input_facts),
+      Some(command) if command.needs_borrowck_facts() => config.override_queries = Some(borrowck::override_queries),
+      _ => {}
+    }
+    diagnostics::capture_diagnostics(config, self.diagnostics.clone());
+  }
+
+  // Borrow errors abort compilation before `after_analysis`, so the borrowck
+  // reports have to be produced here, driving borrowck ourselves.
+  fn after_expansion(
+    &mut self,
+    _compiler: &rustc_interface::interface::Compiler,
+    tcx: TyCtxt<'_>,
+  ) -> rustc_driver::Compilation {
+    let output = &self.output;
+    match self.command() {
+      Some(SaltCommand::Borrowck) => print_borrowck(tcx, &self.diagnostics, output),
+      Some(SaltCommand::Borrows { fn_path }) => print_borrows(tcx, fn_path, output),
+      Some(SaltCommand::Moves) => print_moves(tcx, output),
+      Some(SaltCommand::Drops { fn_path }) => print_drops(tcx, fn_path.as_deref(), output),
+      Some(SaltCommand::Fixes) => print_fixes(tcx, &self.compiler_args, &self.diagnostics, output),
+      Some(SaltCommand::Mismatches) => print_mismatches(tcx, output),
+      Some(SaltCommand::Query { at }) => print_query(tcx, at, output),
+      Some(SaltCommand::Liveness { at }) => print_liveness(tcx, at.as_deref(), &self.diagnostics, output),
+      // errors abort before `after_analysis`, so the report for a crate that fails
+      // is printed here, from the bodies that did type-check
+      None => {
+        if rustc_driver::catch_fatal_errors(|| tcx.ensure_ok().analysis(())).is_err() {
+          self.print_style(tcx, true);
+          return rustc_driver::Compilation::Stop;
+        }
+      }
+    }
+    rustc_driver::Compilation::Continue
+  }
+
   // At the top-level, the Rustc API uses an event-based interface for
   // accessing the compiler at different stages of compilation. In this callback,
   // all the type-checking has completed.
@@ -79,7 +281,9 @@ impl rustc_driver::Callbacks for SaltCallbacks {
     tcx: TyCtxt<'_>,
   ) -> rustc_driver::Compilation {
     // We call our top-level function with access to the type context `tcx` and the CLI arguments.
-    print_inferences(tcx);
+    if self.command().is_none() {
+      self.print_style(tcx, false);
+    }
 
     // Note that you should generally allow compilation to continue. If
     // your plugin is being invoked on a dependency, then you need to ensure
@@ -89,29 +293,209 @@ impl rustc_driver::Callbacks for SaltCallbacks {
   }
 }
 
-#[derive(Serialize, Deserialize)]
+impl SaltCallbacks {
+  pub(crate) fn new(args: SaltPluginArgs, compiler_args: &[String], output: Output) -> Self {
+    SaltCallbacks {
+      args,
+      compiler_args: compiler_args.to_vec(),
+      diagnostics: Captured::default(),
+      output,
+    }
+  }
+
+  fn command(&self) -> Option<&SaltCommand> {
+    self.args.command.as_ref()
+  }
+
+  // the style report, then the hints for the learner if asked for
+  fn print_style(&self, tcx: TyCtxt, partial: bool) {
+    print_inferences(tcx, &self.diagnostics, partial, self.args.cache_dir.as_deref(), &self.output);
+    // cargo only shows the warnings of the packages it was asked to check
+    if self.args.hints && crate_json(tcx).workspace_member {
+      idioms::emit_hints(tcx);
+    }
+  }
+}
+
+// Bumped whenever the shape of `PrintResult` changes, see "Schema" in the README.
+pub const SCHEMA_VERSION: u32 = 7;
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct PrintResult {
-  crate_id: String,
+  pub(crate) schema_version: u32,
+  #[serde(flatten)]
+  pub(crate) krate: CrateJson,
+  #[schemars(with = "VisitorJson")]
   pub(crate) visit_res: serde_json::Value,
+  pub(crate) errors: Vec<DiagnosticJson>,
+  // whether the crate failed to compile, leaving out the bodies with type errors
+  pub(crate) partial: bool,
+  pub(crate) cache: CacheJson,
 }
 
-fn print_inferences(tcx: TyCtxt) {
-  let mut visitor = HirVisitor::new(tcx);
-  tcx.hir_walk_toplevel_module(&mut visitor);
+fn print_inferences(tcx: TyCtxt, diagnostics: &Captured, partial: bool, cache_dir: Option<&Path>, output: &Output) {
+  let mut cache = OwnerCache::load(tcx, cache_dir);
+  let visit = visit_crate(tcx, &mut cache);
 
   let result = PrintResult {
-    crate_id: hash_string(&tcx.crate_name(rustc_hir::def_id::LOCAL_CRATE).to_string()),
-    visit_res: serde_json::to_value(visitor.to_json()).unwrap(),
+    schema_version: SCHEMA_VERSION,
+    krate: crate_json(tcx),
+    visit_res: serde_json::to_value(visit).unwrap(),
+    errors: diagnostics::diagnostics_json(tcx, diagnostics),
+    partial,
+    cache: cache.save(),
   };
-  match serde_json::to_string(&result) {
-    Ok(json) => println!("{}", json),
-    Err(e) => eprintln!("Failed to serialize results: {}", e),
-  }
+  output.emit(&result);
+}
+
+// The schema of the style report, for consumers to validate records against.
+pub fn print_schema() {
+  let schema = schemars::schema_for!(PrintResult);
+  println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BorrowckResult {
+  #[serde(flatten)]
+  pub(crate) krate: CrateJson,
+  pub(crate) errors: Vec<BorrowErrorJson>,
 }
 
-fn hash_string(input: &str) -> String {
-  let mut hasher = DefaultHasher::new();
-  input.hash(&mut hasher);
-  let hash_value = hasher.finish();
-  format!("{}", hash_value).to_string()
-}
\ No newline at end of file
+fn print_borrowck(tcx: TyCtxt, diagnostics: &Captured, output: &Output) {
+  let result = BorrowckResult {
+    krate: crate_json(tcx),
+    errors: borrowck::borrowck_errors(tcx, diagnostics),
+  };
+  output.emit(&result);
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BorrowsResult {
+  #[serde(flatten)]
+  pub(crate) krate: CrateJson,
+  pub(crate) loans: Vec<LoanJson>,
+}
+
+// only the crate that defines the function prints anything
+fn print_borrows(tcx: TyCtxt, fn_path: &str, output: &Output) {
+  let fn_def_id = match borrowck::find_fn(tcx, fn_path) {
+    Ok(Some(fn_def_id)) => fn_def_id,
+    Ok(None) => return,
+    Err(candidates) => {
+      tcx.dcx()
+        .struct_err(format!("`{}` matches more than one function", fn_path))
+        .with_note(format!("candidates: {}", candidates.join(", ")))
+        .with_help("pass a longer path to `--fn`")
+        .emit();
+      return;
+    }
+  };
+  let result = BorrowsResult {
+    krate: crate_json(tcx),
+    loans: borrowck::fn_loans(tcx, fn_def_id),
+  };
+  output.emit(&result);
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MovesResult {
+  #[serde(flatten)]
+  pub(crate) krate: CrateJson,
+  pub(crate) locals: Vec<MovedLocalJson>,
+}
+
+fn print_moves(tcx: TyCtxt, output: &Output) {
+  let result = MovesResult {
+    krate: crate_json(tcx),
+    locals: move_paths::moved_locals(tcx),
+  };
+  output.emit(&result);
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DropsResult {
+  #[serde(flatten)]
+  pub(crate) krate: CrateJson,
+  pub(crate) bodies: Vec<BodyDropsJson>,
+}
+
+fn print_drops(tcx: TyCtxt, fn_path: Option<&str>, output: &Output) {
+  let result = DropsResult {
+    krate: crate_json(tcx),
+    bodies: drop_order::body_drops(tcx, fn_path),
+  };
+  output.emit(&result);
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FixesResult {
+  #[serde(flatten)]
+  pub(crate) krate: CrateJson,
+  pub(crate) fixes: Vec<FixJson>,
+}
+
+fn print_fixes(tcx: TyCtxt, compiler_args: &[String], diagnostics: &Captured, output: &Output) {
+  let result = FixesResult {
+    krate: crate_json(tcx),
+    fixes: fix_candidates::verified_fixes(tcx, compiler_args, diagnostics),
+  };
+  output.emit(&result);
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MismatchesResult {
+  #[serde(flatten)]
+  pub(crate) krate: CrateJson,
+  pub(crate) mismatches: BTreeMap<String, MismatchJson>,
+}
+
+fn print_mismatches(tcx: TyCtxt, output: &Output) {
+  let result = MismatchesResult {
+    krate: crate_json(tcx),
+    mismatches: type_mismatch::type_mismatches(tcx),
+  };
+  output.emit(&result);
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct QueryResult {
+  #[serde(flatten)]
+  pub(crate) krate: CrateJson,
+  pub(crate) ownership: OwnershipJson,
+}
+
+fn print_query(tcx: TyCtxt, at: &str, output: &Output) {
+  let Some(position) = ownership_query::Position::parse(at) else {
+    eprintln!("Expected a position as file.rs:LINE:COL, got {}", at);
+    return;
+  };
+  // only the crate containing the position prints a result
+  let Some(ownership) = ownership_query::ownership_at(tcx, &position) else { return };
+  let result = QueryResult {
+    krate: crate_json(tcx),
+    ownership,
+  };
+  output.emit(&result);
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LivenessResult {
+  #[serde(flatten)]
+  pub(crate) krate: CrateJson,
+  pub(crate) chains: Vec<LivenessJson>,
+}
+
+fn print_liveness(tcx: TyCtxt, at: Option<&str>, diagnostics: &Captured, output: &Output) {
+  let position = match at.map(ownership_query::Position::parse) {
+    Some(None) => {
+      eprintln!("Expected a position as file.rs:LINE:COL, got {}", at.unwrap());
+      return;
+    }
+    position => position.flatten(),
+  };
+  let result = LivenessResult {
+    krate: crate_json(tcx),
+    chains: liveness_chain::liveness_chains(tcx, position.as_ref(), diagnostics),
+  };
+  output.emit(&result);
+}
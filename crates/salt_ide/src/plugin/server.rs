@@ -0,0 +1,534 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use clap::ValueEnum;
+use rustc_plugin::RustcPlugin;
+
+use crate::plugin::anonymize::Identifiers;
+use crate::plugin::capabilities::capabilities;
+use crate::plugin::print_result::{NoAnalysis, SaltPlugin, SaltPluginArgs};
+
+// set while the server records invocations, to the file cargo's wrapper calls append to
+pub const RECORD_VAR: &str = "SALT_SERVER_RECORD";
+
+// set for the server's own process compiling one crate, to the analysis' plugin args as JSON
+pub const COMPILE_VAR: &str = "SALT_SERVER_COMPILE";
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+#[derive(Parser)]
+pub struct ServerArgs {
+    /// Identify items and files by hash, by readable paths, or both, for every request
+    #[arg(long, value_enum, default_value_t)]
+    identifiers: Identifiers,
+
+    /// Analyze every item again on each request instead of reusing the results cached under `target/salt/cache`
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Arguments for the `cargo check` recording each crate's compiler invocation, eg. `--all-targets`
+    #[clap(last = true)]
+    cargo_args: Vec<String>,
+}
+
+// how cargo compiled a workspace crate, replayed in a process of its own for every request about it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Invocation {
+    package: String,
+    crate_name: String,
+    // the crate root, eg. `src/main.rs`, as an absolute path
+    root: PathBuf,
+    cwd: PathBuf,
+    args: Vec<String>,
+    // what cargo tells rustc through the environment, eg. for `env!("CARGO_PKG_NAME")`
+    env: BTreeMap<String, String>,
+}
+
+// a crate the server can analyze, as listed by `crates`
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct TargetJson {
+    pub(crate) package: String,
+    pub(crate) crate_name: String,
+    pub(crate) root: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Result(Value),
+    Error(RpcError),
+}
+
+#[derive(Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError { code, message: message.into() }
+    }
+}
+
+// which crates an analysis request is about, and the analysis' own arguments, eg. `["--fn", "main"]`
+#[derive(Deserialize, Default)]
+struct AnalyzeParams {
+    file: Option<PathBuf>,
+    package: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+// answers requests by re-running rustc on the selected crates; no compiler state outlives a request
+pub struct Server {
+    root: PathBuf,
+    cargo_args: Vec<String>,
+    identifiers: Identifiers,
+    no_cache: bool,
+    // the same per-owner cache `cargo salt` keeps, in cargo's target directory
+    cache_dir: Option<PathBuf>,
+    // recorded on the first request that needs them, and again on `reload` or when the layout changes
+    invocations: Option<Vec<Invocation>>,
+    // the workspace's manifests and the layout they had when the invocations were recorded
+    manifests: Vec<PathBuf>,
+    layout: Layout,
+}
+
+// what decides which crates cargo compiles and how: the manifests' modification times and the
+// source files next to them, since cargo also finds targets such as `src/bin/*.rs` by their path
+#[derive(PartialEq, Eq, Default)]
+struct Layout {
+    modified: Vec<Option<SystemTime>>,
+    sources: BTreeSet<PathBuf>,
+}
+
+impl Server {
+    pub fn new(args: ServerArgs) -> io::Result<Self> {
+        Ok(Server {
+            root: env::current_dir()?,
+            cargo_args: args.cargo_args,
+            identifiers: args.identifiers,
+            no_cache: args.no_cache,
+            cache_dir: None,
+            invocations: None,
+            manifests: Vec::new(),
+            layout: Layout::default(),
+        })
+    }
+
+    // one request per line on stdin, one response per line on stdout, until `shutdown` or EOF
+    pub fn serve(&mut self) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (response, shutdown) = self.handle(&line);
+            if let Some(response) = response {
+                writeln!(stdout, "{}", serde_json::to_string(&response)?)?;
+                stdout.flush()?;
+            }
+            if shutdown {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // the response to a request, if it wasn't a notification, and whether it asked the server to stop
+    fn handle(&mut self, line: &str) -> (Option<Response>, bool) {
+        let request = match serde_json::from_str::<Value>(line) {
+            Ok(value) => serde_json::from_value::<Request>(value)
+                .map_err(|e| RpcError::new(INVALID_REQUEST, e.to_string())),
+            Err(e) => Err(RpcError::new(PARSE_ERROR, e.to_string())),
+        };
+        let request = match request {
+            Ok(request) => request,
+            Err(error) => return (Some(response(Value::Null, Err(error))), false),
+        };
+        let shutdown = request.method == "shutdown";
+        let result = match request.method.as_str() {
+            "capabilities" => Ok(serde_json::to_value(capabilities()).unwrap()),
+            "crates" => self.targets(),
+            "reload" => {
+                self.invocations = None;
+                self.targets()
+            }
+            "shutdown" => Ok(Value::Null),
+            analysis if capabilities().analyses.iter().any(|name| name == analysis) => {
+                self.analyze(analysis, request.params)
+            }
+            method => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        };
+        (request.id.map(|id| response(id, result)), shutdown)
+    }
+
+    fn targets(&mut self) -> Result<Value, RpcError> {
+        let targets = self
+            .invocations()?
+            .iter()
+            .map(|invocation| TargetJson {
+                package: invocation.package.clone(),
+                crate_name: invocation.crate_name.clone(),
+                root: invocation.root.clone(),
+            })
+            .collect::<Vec<_>>();
+        Ok(serde_json::to_value(targets).unwrap())
+    }
+
+    // runs the analysis on every recorded crate the params select, returning their records
+    fn analyze(&mut self, analysis: &str, params: Value) -> Result<Value, RpcError> {
+        let params: AnalyzeParams = if params.is_null() {
+            AnalyzeParams::default()
+        } else {
+            serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?
+        };
+        // the style report has no subcommand, and its top-level flags are fixed when the server starts
+        if analysis == "style" && !params.args.is_empty() {
+            return Err(RpcError::new(INVALID_PARAMS, "style takes no args"));
+        }
+        let identifiers = format!("--identifiers={}", self.identifiers.to_possible_value().unwrap().get_name());
+        let command_line = ["salt", &identifiers]
+            .into_iter()
+            .chain((analysis != "style").then_some(analysis))
+            .chain(params.args.iter().map(String::as_str));
+        let mut plugin_args = SaltPluginArgs::try_parse_from(command_line)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+
+        let file = match &params.file {
+            Some(file) => Some(
+                fs::canonicalize(self.root.join(file))
+                    .map_err(|e| RpcError::new(INVALID_PARAMS, format!("{}: {}", file.display(), e)))?,
+            ),
+            None => None,
+        };
+        let selected = select(self.invocations()?, params.package.as_deref(), file.as_deref());
+        plugin_args.cache_dir = self.cache_dir.clone();
+        if selected.is_empty() {
+            return Err(RpcError::new(INVALID_PARAMS, "No recorded crate matches the file or package"));
+        }
+
+        // the selected crates read the metadata of the workspace crates they depend on, which
+        // is only up to date with the sources on disk once those are compiled again too
+        for dependency in dependencies(self.invocations()?, &selected) {
+            compile(&dependency, None)?;
+        }
+        let mut records = Vec::new();
+        for invocation in selected {
+            records.extend(compile(&invocation, Some(&plugin_args))?);
+        }
+        Ok(Value::Array(records))
+    }
+
+    fn invocations(&mut self) -> Result<&[Invocation], RpcError> {
+        if self.invocations.is_some() && layout(&self.manifests) != self.layout {
+            self.invocations = None;
+        }
+        if self.invocations.is_none() {
+            let (invocations, target_dir, manifests) = record_invocations(&self.root, &self.cargo_args)
+                .map_err(|e| RpcError::new(INTERNAL_ERROR, format!("Failed to record invocations: {}", e)))?;
+            self.invocations = Some(invocations);
+            self.layout = layout(&manifests);
+            self.manifests = manifests;
+            self.cache_dir = (!self.no_cache).then(|| target_dir.join("salt").join("cache"));
+        }
+        Ok(self.invocations.as_deref().unwrap())
+    }
+
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Response {
+    let outcome = match result {
+        Ok(value) => Outcome::Result(value),
+        Err(error) => Outcome::Error(error),
+    };
+    Response { jsonrpc: "2.0", id, outcome }
+}
+
+// the crates of the package, or those whose sources contain the file, preferring the crate
+// the file is the root of. A file shared by several crates selects all of them.
+fn select(invocations: &[Invocation], package: Option<&str>, file: Option<&Path>) -> Vec<Invocation> {
+    let in_package = invocations
+        .iter()
+        .filter(|invocation| package.is_none_or(|package| invocation.package == package));
+    let Some(file) = file else { return in_package.cloned().collect() };
+    let containing = in_package
+        .filter(|invocation| invocation.root.parent().is_some_and(|dir| file.starts_with(dir)))
+        .collect::<Vec<_>>();
+    match containing.iter().find(|invocation| invocation.root == file) {
+        Some(rooted) => vec![(*rooted).clone()],
+        None => containing.into_iter().cloned().collect(),
+    }
+}
+
+// the workspace crates the selected ones are compiled against, each after its own dependencies,
+// leaving out the selected crates, which are compiled in the order cargo compiled them
+fn dependencies(invocations: &[Invocation], selected: &[Invocation]) -> Vec<Invocation> {
+    let mut ordered = Vec::new();
+    for invocation in selected {
+        add_dependencies(invocations, invocation, &mut ordered);
+    }
+    let selected = selected.iter().filter_map(Invocation::metadata).collect::<Vec<_>>();
+    ordered.retain(|dependency| dependency.metadata().is_none_or(|metadata| !selected.contains(&metadata)));
+    ordered
+}
+
+fn add_dependencies(invocations: &[Invocation], invocation: &Invocation, ordered: &mut Vec<Invocation>) {
+    for metadata in externs(&invocation.args) {
+        // crates outside the workspace aren't recorded, and don't change
+        let Some(dependency) = invocations.iter().find(|candidate| candidate.metadata().as_deref() == Some(metadata)) else {
+            continue;
+        };
+        if !ordered.iter().any(|done| done.metadata().as_deref() == Some(metadata)) {
+            add_dependencies(invocations, dependency, ordered);
+            ordered.push(dependency.clone());
+        }
+    }
+}
+
+impl Invocation {
+    // the metadata a library's invocation writes, which is what other crates' `--extern` names
+    fn metadata(&self) -> Option<PathBuf> {
+        let out_dir = arg_value(&self.args, "--out-dir")?;
+        let extra_filename = self.args.iter().find_map(|arg| arg.strip_prefix("extra-filename=")).unwrap_or_default();
+        Some(Path::new(out_dir).join(format!("lib{}{}.rmeta", self.crate_name, extra_filename)))
+    }
+}
+
+// the files of every `--extern name=path`
+fn externs(args: &[String]) -> impl Iterator<Item = &Path> {
+    args.windows(2)
+        .filter(|pair| pair[0] == "--extern")
+        .filter_map(|pair| pair[1].split_once('='))
+        .map(|(_, path)| Path::new(path))
+}
+
+// Every request compiles each crate again in a new process, a cold re-run that reads the sources
+// on disk and reuses the invocation's incremental cache, so rustc only recomputes the queries that
+// changed since the last request. The process is the server's own executable, run in the directory
+// and with the variables cargo gave rustc, and it prints the analysis' records on stdout.
+// A crate with errors still gives the records of what did compile, but a session that
+// panicked, or stopped before the analysis ran, fails the request.
+fn compile(invocation: &Invocation, plugin_args: Option<&SaltPluginArgs>) -> Result<Vec<Value>, RpcError> {
+    let internal_error = |e: io::Error| RpcError::new(INTERNAL_ERROR, format!("Failed to compile {}: {}", invocation.crate_name, e));
+    let mut command = Command::new(env::current_exe().map_err(internal_error)?);
+    command
+        .args(&invocation.args)
+        .current_dir(&invocation.cwd)
+        .env(COMPILE_VAR, serde_json::to_string(&plugin_args).unwrap())
+        .stderr(Stdio::inherit());
+    // cargo's variables for the server itself, eg. under `cargo run`, aren't the crate's
+    for (key, _) in env::vars().filter(|(key, _)| cargo_var(key) && !invocation.env.contains_key(key)) {
+        command.env_remove(key);
+    }
+    command.envs(&invocation.env);
+    let output = command.output().map_err(internal_error)?;
+    let records = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect::<Vec<Value>>();
+    let reported_errors = output.status.code() == Some(rustc_driver::EXIT_FAILURE);
+    if output.status.success() || (reported_errors && plugin_args.is_some() && !records.is_empty()) {
+        return Ok(records);
+    }
+    Err(RpcError::new(
+        INTERNAL_ERROR,
+        format!("Compiling {} failed ({}), rustc's errors are on the server's stderr", invocation.crate_name, output.status),
+    ))
+}
+
+// The server compiling one crate in its own process: `args` are rustc's path and its arguments,
+// and `plugin_args` the analysis' arguments as JSON, or `null` for a dependency of the crates
+// being analyzed, which is only compiled. Returns rustc's exit code.
+pub fn compile_crate(plugin_args: &str, args: impl Iterator<Item = String>) -> i32 {
+    let compiler_args = args.collect::<Vec<_>>();
+    let plugin_args = match serde_json::from_str::<Option<SaltPluginArgs>>(plugin_args) {
+        Ok(plugin_args) => plugin_args,
+        Err(e) => {
+            eprintln!("Invalid {}: {}", COMPILE_VAR, e);
+            return 1;
+        }
+    };
+    rustc_driver::catch_with_exit_code(move || match plugin_args {
+        Some(plugin_args) => {
+            let _ = SaltPlugin.run(compiler_args, plugin_args);
+        }
+        None => rustc_driver::run_compiler(&compiler_args, &mut NoAnalysis),
+    })
+}
+
+// runs `cargo check` on the workspace with the server as rustc's wrapper, so each crate's
+// invocation is recorded under `target/salt/server`. Also returns cargo's target directory
+// and the workspace's manifests: each member's `Cargo.toml` and the `Cargo.lock`.
+fn record_invocations(root: &Path, cargo_args: &[String]) -> io::Result<(Vec<Invocation>, PathBuf, Vec<PathBuf>)> {
+    let metadata = Command::new("cargo")
+        .args(["metadata", "--no-deps", "--format-version", "1", "--offline"])
+        .current_dir(root)
+        .stderr(Stdio::inherit())
+        .output()?;
+    let metadata: Value = serde_json::from_slice(&metadata.stdout)?;
+    let target_dir = metadata["target_directory"].as_str().map(PathBuf::from).unwrap_or_else(|| root.join("target"));
+    let dir = target_dir.join("salt").join("server");
+    fs::create_dir_all(&dir)?;
+    let packages = metadata["packages"].as_array().into_iter().flatten();
+    let mut manifests = packages
+        .filter_map(|package| package["manifest_path"].as_str())
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    let workspace_root = metadata["workspace_root"].as_str().map(PathBuf::from).unwrap_or_else(|| root.to_path_buf());
+    manifests.push(workspace_root.join("Cargo.lock"));
+
+    // cargo only calls the wrapper for crates it compiles, so the workspace's own are cleaned first
+    let members = metadata["packages"].as_array().into_iter().flatten().filter_map(|package| package["name"].as_str());
+    for member in members {
+        Command::new("cargo")
+            .args(["clean", "-q", "--offline", "-p", member, "--target-dir"])
+            .arg(&dir)
+            .current_dir(root)
+            .stdout(Stdio::null())
+            .status()?;
+    }
+
+    let record = dir.join("invocations.jsonl");
+    File::create(&record)?;
+    // stdout carries the protocol, so cargo's output goes to stderr
+    Command::new("cargo")
+        .args(["check", "-q", "--all", "--target-dir"])
+        .arg(&dir)
+        .args(cargo_args)
+        .env("RUSTC_WORKSPACE_WRAPPER", env::current_exe()?)
+        .env(RECORD_VAR, &record)
+        .current_dir(root)
+        .stdout(Stdio::from(io::stderr()))
+        .status()?;
+
+    let mut invocations = Vec::new();
+    for line in fs::read_to_string(&record)?.lines() {
+        invocations.push(serde_json::from_str(line)?);
+    }
+    Ok((invocations, target_dir, manifests))
+}
+
+fn layout(manifests: &[PathBuf]) -> Layout {
+    let modified = manifests
+        .iter()
+        .map(|manifest| fs::metadata(manifest).and_then(|metadata| metadata.modified()).ok())
+        .collect();
+    let mut sources = BTreeSet::new();
+    for manifest in manifests.iter().filter(|manifest| manifest.ends_with("Cargo.toml")) {
+        if let Some(dir) = manifest.parent() {
+            add_sources(dir, &mut sources);
+        }
+    }
+    Layout { modified, sources }
+}
+
+// the `.rs` files under `dir`, leaving out build output and hidden directories such as `.git`
+fn add_sources(dir: &Path, sources: &mut BTreeSet<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => add_sources(&path, sources),
+            _ if name.ends_with(".rs") => {
+                sources.insert(path);
+            }
+            _ => {}
+        }
+    }
+}
+
+// The server as cargo's wrapper: `args` are rustc's path and its arguments. Records the
+// invocation of each crate, then compiles it with the real rustc and returns its exit code.
+pub fn record_invocation(record: &Path, mut args: impl Iterator<Item = String>) -> i32 {
+    let Some(rustc) = args.next() else { return 1 };
+    let args = args.collect::<Vec<_>>();
+    // cargo also calls the wrapper to ask rustc about the target, as crate `___`
+    if let Some(crate_name) = arg_value(&args, "--crate-name").filter(|name| *name != "___") {
+        if let Err(e) = append_invocation(record, &rustc, &args, crate_name) {
+            eprintln!("Failed to record {}: {}", crate_name, e);
+        }
+    }
+    match Command::new(&rustc).args(&args).status() {
+        Ok(status) => status.code().unwrap_or(1),
+        Err(e) => {
+            eprintln!("Failed to run {}: {}", rustc, e);
+            1
+        }
+    }
+}
+
+fn append_invocation(record: &Path, rustc: &str, args: &[String], crate_name: &str) -> io::Result<()> {
+    let cwd = env::current_dir()?;
+    let root = args
+        .iter()
+        .find(|arg| arg.ends_with(".rs") && !arg.starts_with('-'))
+        .ok_or_else(|| io::Error::other("no crate root"))?;
+    // the server runs rustc from its own executable, so the sysroot has to be passed along
+    let mut compiler_args = std::iter::once(rustc.to_string()).chain(args.iter().cloned()).collect::<Vec<_>>();
+    if arg_value(args, "--sysroot").is_none() {
+        let sysroot = Command::new(rustc).args(["--print", "sysroot"]).output()?;
+        compiler_args.extend(["--sysroot".to_string(), String::from_utf8_lossy(&sysroot.stdout).trim().to_string()]);
+    }
+    let invocation = Invocation {
+        package: env::var("CARGO_PKG_NAME").unwrap_or_default(),
+        crate_name: crate_name.to_string(),
+        root: fs::canonicalize(cwd.join(root))?,
+        cwd,
+        args: compiler_args,
+        // the jobserver cargo shares through CARGO_MAKEFLAGS is gone by the time the server compiles
+        env: env::vars().filter(|(key, _)| cargo_var(key) && key != "CARGO_MAKEFLAGS").collect(),
+    };
+
+    // cargo compiles crates in parallel, so each wrapper holds the lock while it appends
+    let mut file = OpenOptions::new().append(true).create(true).open(record)?;
+    file.lock()?;
+    writeln!(file, "{}", serde_json::to_string(&invocation)?)?;
+    file.unlock()
+}
+
+// the variables cargo sets for rustc
+fn cargo_var(key: &str) -> bool {
+    key.starts_with("CARGO") || key == "OUT_DIR"
+}
+
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let position = args.iter().position(|arg| arg == flag)?;
+    args.get(position + 1).map(String::as_str)
+}
@@ -0,0 +1,70 @@
+#![allow(warnings)]
+
+fn main() {}
+
+// E0502
+fn push_while_borrowed() {
+    let mut v = vec![1, 2, 3];
+    let first = &v[0];
+    v.push(4);
+    println!("{}", first);
+}
+
+// E0499
+fn two_mut_borrows() {
+    let mut s = String::new();
+    let a = &mut s;
+    let b = &mut s;
+    a.push('x');
+    b.push('y');
+}
+
+// E0505
+fn move_while_borrowed() {
+    let s = String::from("hi");
+    let r = &s;
+    let t = s;
+    println!("{} {}", r, t);
+}
+
+// E0597
+fn dropped_while_borrowed() -> u32 {
+    let r;
+    {
+        let x = 5;
+        r = &x;
+    }
+    *r
+}
+
+fn no_errors() {
+    let mut x = 1;
+    let y = &mut x;
+    *y += 1;
+    println!("{}", x);
+}
+
+struct Counter(u32);
+
+impl Counter {
+    fn new() -> Self {
+        Counter(0)
+    }
+}
+
+struct Meter(u32);
+
+impl Meter {
+    fn new() -> Self {
+        Meter(0)
+    }
+}
+
+// E0502 that NLL reports but Polonius accepts, the returned borrow only lives on one path
+fn first_or_insert(v: &mut Vec<u32>) -> &u32 {
+    if let Some(first) = v.first() {
+        return first;
+    }
+    v.push(0);
+    &v[0]
+}
@@ -0,0 +1,26 @@
+#![allow(warnings)]
+
+fn main() {}
+
+// E0597
+fn dropped_while_borrowed() -> u32 {
+    let r;
+    {
+        let x = 5;
+        r = &x;
+    }
+    *r
+}
+
+// E0716
+fn temporary_dropped_while_borrowed() {
+    let r: &String;
+    r = &String::from("hi");
+    println!("{}", r);
+}
+
+fn extended_temporary() {
+    let r = &String::from("hi");
+    let n = String::from("hi").len();
+    println!("{} {}", r, n);
+}
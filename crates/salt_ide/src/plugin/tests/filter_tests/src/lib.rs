@@ -0,0 +1,3 @@
+pub fn quadruple(x: u32) -> u32 {
+    helper::double(helper::double(x))
+}
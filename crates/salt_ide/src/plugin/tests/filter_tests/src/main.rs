@@ -0,0 +1,3 @@
+fn main() {
+    println!("{}", filter_tests::quadruple(3));
+}
@@ -0,0 +1,48 @@
+#![allow(warnings)]
+
+fn main() {}
+
+// E0502
+fn push_while_borrowed() {
+    let mut v = vec![1, 2, 3];
+    let first = &v[0];
+    v.push(4);
+    println!("{}", first);
+}
+
+// E0499
+fn two_mut_borrows() {
+    let mut s = String::new();
+    let a = &mut s;
+    let b = &mut s;
+    a.push('x');
+    b.push('y');
+}
+
+// E0505
+fn move_while_borrowed() {
+    let s = String::from("hi");
+    let r = &s;
+    let t = s;
+    println!("{} {}", r, t);
+}
+
+fn consume(s: String) {}
+
+// E0382
+fn use_after_move() {
+    let s = String::from("hi");
+    consume(s);
+    println!("{}", s);
+}
+
+// E0502 and E0382 in the same body, each fixed on its own
+fn two_errors() {
+    let mut v = vec![1, 2, 3];
+    let first = &v[0];
+    v.push(4);
+    println!("{}", first);
+    let s = String::from("hi");
+    consume(s);
+    println!("{}", s);
+}
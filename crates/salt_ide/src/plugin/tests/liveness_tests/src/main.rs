@@ -0,0 +1,41 @@
+#![allow(warnings)]
+
+fn main() {}
+
+// kept alive by a later use through another reference
+fn through_reference() {
+    let mut v = vec![1];
+    let first = &v[0];
+    let alias = first;
+    v.push(2);
+    println!("{}", alias);
+}
+
+struct Holder<'a> {
+    r: &'a String,
+}
+
+// kept alive by a struct holding the borrow
+fn through_struct() {
+    let mut s = String::new();
+    let h = Holder { r: &s };
+    s.push('a');
+    println!("{}", h.r);
+}
+
+fn first_of(v: &Vec<i32>) -> &i32 {
+    &v[0]
+}
+
+// kept alive by the reference the call returns
+fn through_return() {
+    let mut v = vec![1];
+    let f = first_of(&v);
+    v.push(2);
+    println!("{}", f);
+}
+
+// kept alive by the signature, since the reborrow is returned
+fn first_mut(v: &mut Vec<i32>) -> &mut i32 {
+    &mut v[0]
+}
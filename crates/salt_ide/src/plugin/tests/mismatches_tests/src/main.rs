@@ -0,0 +1,75 @@
+#![allow(warnings)]
+
+fn main() {}
+
+fn takes_str(s: &str) {}
+
+fn takes_string(s: String) {}
+
+// `&` fixes it, `&String` derefs to `&str`
+fn owned_for_ref() {
+    let s = String::from("hi");
+    takes_str(s);
+}
+
+// `.to_string()` or `.into()`
+fn ref_for_owned() {
+    takes_string("hi");
+}
+
+// `*` gets the `i32` out of the box, one autoderef step away
+fn boxed() {
+    let b = Box::new(5i32);
+    let x: i32 = b;
+}
+
+// widening needs `.into()`
+fn widen() {
+    let n: i64 = 5i32;
+}
+
+// `.as_ref()` borrows the contents of the option
+fn option_ref(o: Option<String>) -> Option<&'static String> {
+    let r: Option<&String> = o;
+    None
+}
+
+fn returns_str() -> String {
+    "hi"
+}
+
+struct Name<'a>(&'a str);
+
+impl<'a> std::ops::Deref for Name<'a> {
+    type Target = &'a str;
+
+    fn deref(&self) -> &&'a str {
+        &self.0
+    }
+}
+
+// `&` fixes it, `&Name` derefs through the `&str` it holds to `str`
+fn named<'a>(name: Name<'a>) -> &'a str {
+    name
+}
+
+trait Shape {}
+
+impl Shape for i32 {}
+
+fn takes_ptr(f: fn() -> i32) {}
+
+fn answer() -> i32 {
+    42
+}
+
+// only the last line is a mismatch, rustc coerces everything before it
+fn coerced(mut n: i32) {
+    let shape: &dyn Shape = &n;
+    let boxed: Box<dyn Shape> = Box::new(n);
+    takes_ptr(answer);
+    let closure: fn() -> i32 = || 1;
+    let raw: *mut i32 = &mut n;
+    let slice: Box<[i32]> = Box::new([1, 2]);
+    let s: String = n;
+}
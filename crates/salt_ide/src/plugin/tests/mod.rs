@@ -1,15 +1,28 @@
-// done with hashing disabled
-
+#[cfg(test)]
 mod test_utils;
 
 #[cfg(test)]
 mod test {
 use crate::plugin::visit_hir::*;
+use crate::plugin::print_result::*;
+use crate::plugin::anonymize::hash_file_name;
+use crate::plugin::capabilities::CapabilitiesJson;
+use crate::plugin::server::TargetJson;
+use crate::plugin::liveness_chain::LivenessJson;
 use std::process::Command;
 use crate::plugin::tests::test_utils::*;
 use std::collections::HashMap;
 
 const PATH: &str = "src/plugin/tests/unit_tests";
+const BORROWCK_PATH: &str = "src/plugin/tests/borrowck_tests";
+const MOVES_PATH: &str = "src/plugin/tests/moves_tests";
+const DROPS_PATH: &str = "src/plugin/tests/drops_tests";
+const FIXES_PATH: &str = "src/plugin/tests/fixes_tests";
+const MISMATCHES_PATH: &str = "src/plugin/tests/mismatches_tests";
+const QUERY_PATH: &str = "src/plugin/tests/query_tests";
+const LIVENESS_PATH: &str = "src/plugin/tests/liveness_tests";
+const FILTER_PATH: &str = "src/plugin/tests/filter_tests";
+const TEST_PROJECT_PATH: &str = "../../test-project";
 
     #[test]
     fn reinstall_salt() {
@@ -30,8 +43,31 @@ const PATH: &str = "src/plugin/tests/unit_tests";
     fn test() {
         let visit = run_salt(PATH);
         println!("{}", serde_json::to_string_pretty(&visit).unwrap());
+        let main_rs = |line_start, line_end| source(PATH, "src/main.rs", line_start, line_end);
+        let other_rs = |line_start, line_end| source(PATH, "src/other.rs", line_start, line_end);
+        // a loop without labels, `break`s or `continue`s
+        let plain_loop = |source, def_id: &String, lines, depth, kind: &str, iterates: Option<&str>| BlockJson::Loop {
+            source,
+            def_id: def_id.clone(),
+            lines,
+            depth,
+            kind: kind.to_string(),
+            labeled: false,
+            breaks: 0,
+            continues: 0,
+            break_with_value: false,
+            iterates: iterates.map(str::to_string),
+        };
+        // the same loop with a label, `break`s or `continue`s
+        let jumps = |mut block: BlockJson, is_labeled, break_count, continue_count, with_value| {
+            if let BlockJson::Loop { labeled, breaks, continues, break_with_value, .. } = &mut block {
+                (*labeled, *breaks, *continues, *break_with_value) = (is_labeled, break_count, continue_count, with_value);
+            }
+            block
+        };
 
         let main = BlockJson::Def {
+            source: main_rs(6, 9),
             params: serde_json::to_value(Params::default()).unwrap(),
             ret: serde_json::to_value(Return::default()).unwrap(),
             unsafety: false,
@@ -42,6 +78,7 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         let main_json = compare_fn("main", &main, &visit.fns);
 
         let test_json: BlockJson = BlockJson::Def {
+            source: main_rs(12, 16),
             params: serde_json::to_value(Params::default()).unwrap(),
             ret: serde_json::to_value(Return::default()).unwrap(),
             unsafety: false,
@@ -50,10 +87,11 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         };
 
         let test_def = compare_fn("test_1", &test_json, &visit.fns);
-        assert!(visit.loops.contains(&BlockJson::Loop{ def_id: test_def.clone(), lines: 3, depth: 1}),
+        assert!(visit.loops.contains(&plain_loop(main_rs(13, 15), &test_def, 3, 1, "While", None)),
             "Loop block not found in {:?}", visit.loops);
 
         let impl_closure_json = BlockJson::Def {
+            source: main_rs(19, 22),
             params: serde_json::to_value(Params {
                 closure_traits: vec!["Fn".to_string()],
                 ty_kinds: vec![(false, "Param".to_string())]
@@ -71,6 +109,7 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         compare_fn("impl_closure", &impl_closure_json, &visit.fns);
         
         let fn_mut = BlockJson::Def {
+            source: main_rs(24, 24),
             params: serde_json::to_value(Params {
                 closure_traits: vec!["FnMut".to_string()],
                 ty_kinds: vec![(true, "Param".to_string())]
@@ -84,6 +123,7 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         compare_fn("fn_mut", &fn_mut, &visit.fns);
 
         let dyna_clos = BlockJson::Def {
+            source: main_rs(27, 29),
             params: serde_json::to_value(Params {
                 closure_traits: vec!["FnOnce".to_string()],
                 ty_kinds: vec![(false, "Adt".to_string()), (false, "Int".to_string())]
@@ -101,6 +141,7 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         compare_fn("dyna_clos", &dyna_clos, &visit.fns);
 
         let mut_ref = BlockJson::Def {
+            source: main_rs(32, 35),
             params: serde_json::to_value(Params {
                 closure_traits: Vec::new(),
                 ty_kinds: vec![(true, "Ref".to_string())]
@@ -118,6 +159,7 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         compare_fn("mut_ref", &mut_ref, &visit.fns);
 
         let mut_val_recurse = BlockJson::Def {
+            source: main_rs(37, 41),
             params: serde_json::to_value(Params {
                 closure_traits: Vec::new(),
                 ty_kinds: vec![(true, "Uint".to_string())]
@@ -131,6 +173,7 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         compare_fn("mut_val_recurse", &mut_val_recurse, &visit.fns);
 
         let unsafe_param = BlockJson::Def {
+            source: main_rs(43, 47),
             params: serde_json::to_value(Params {
                 closure_traits: Vec::new(),
                 ty_kinds: vec![(true, "Adt".to_string())]
@@ -144,6 +187,7 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         compare_fn("unsafe_param", &unsafe_param, &visit.fns);
 
         let unsafe_fn = BlockJson::Def {
+            source: main_rs(50, 52),
             params: serde_json::to_value(Params {
                 closure_traits: Vec::new(),
                 ty_kinds: vec![(false, "Uint".to_string())]
@@ -157,6 +201,7 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         let unsafe_json = compare_fn("unsafe_fn", &unsafe_fn, &visit.fns);
 
         let call_unsafe = BlockJson::Def {
+            source: main_rs(54, 58),
             params: serde_json::to_value(Params {
                 closure_traits: Vec::new(),
                 ty_kinds: vec![(false, "Uint".to_string())]
@@ -169,10 +214,11 @@ const PATH: &str = "src/plugin/tests/unit_tests";
 
         let call_unjson = compare_fn("call_unsafe", &call_unsafe, &visit.fns);
         assert!(visit.calls[&unsafe_json] == HashMap::from([(call_unjson.clone(), 1)]));
-        assert!(visit.unsafe_blocks.contains(&BlockJson::Unsafe{ def_id: call_unjson, lines: 3 , depth : 1}),
+        assert!(visit.unsafe_blocks.contains(&BlockJson::Unsafe{ source: main_rs(55, 57), def_id: call_unjson, lines: 3 , depth : 1}),
             "Unsafe block not found in {:?}", visit.unsafe_blocks);
 
         let loopception = BlockJson::Def {
+            source: main_rs(69, 76),
             params: serde_json::to_value(Params {
                 closure_traits: Vec::new(),
                 ty_kinds: vec![(false, "Adt".to_string())]
@@ -184,9 +230,9 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         };
 
         let loopjson = compare_fn("loopception", &loopception, &visit.fns);
-        assert!(visit.loops.contains(&BlockJson::Loop{ def_id: loopjson.clone(), lines: 5, depth: 1}),
+        assert!(visit.loops.contains(&plain_loop(main_rs(70, 74), &loopjson, 5, 1, "For", Some("Iterator"))),
             "Loop block not found in {:?}", visit.loops);
-        assert!(visit.loops.contains(&BlockJson::Loop{ def_id: loopjson.clone(), lines: 3, depth: 2}),
+        assert!(visit.loops.contains(&plain_loop(main_rs(71, 73), &loopjson, 3, 2, "For", Some("Iterator"))),
             "Loop block not found in {:?}", visit.loops);
         assert!(visit.calls[&test_def] == HashMap::from([
             (main_json, 2),
@@ -194,6 +240,7 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         ]));
 
         let looperoni = BlockJson::Def {
+            source: main_rs(78, 88),
             params: serde_json::to_value(Params {
                 closure_traits: Vec::new(),
                 ty_kinds: vec![(false, "Adt".to_string())]
@@ -206,13 +253,14 @@ const PATH: &str = "src/plugin/tests/unit_tests";
 
         let looperjson = compare_fn("looperoni", &looperoni, &visit.fns);
 
-        assert!(visit.loops.contains(&BlockJson::Loop{ def_id: looperjson.clone(), lines: 9, depth: 1}),
+        assert!(visit.loops.contains(&plain_loop(main_rs(79, 87), &looperjson, 9, 1, "For", Some("Iterator"))),
             "Loop block not found in {:?}", visit.loops);
-        assert!(visit.loops.contains(&BlockJson::Loop{ def_id: looperjson.clone(), lines: 3, depth: 2}),
+        assert!(visit.loops.contains(&plain_loop(main_rs(81, 83), &looperjson, 3, 2, "For", Some("Iterator"))),
             "Loop block not found in {:?}", visit.loops);
 
         
         let match_test = BlockJson::Def {
+            source: main_rs(91, 97),
             params: serde_json::to_value(Params {
                 closure_traits: Vec::new(),
                 ty_kinds: vec![(false, "Uint".to_string())]
@@ -224,10 +272,13 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         };
 
         let match_json = compare_fn("match_test", &match_test, &visit.fns);
-        assert!(visit.matches.contains(&BlockJson::Match{ def_id: match_json.clone(), lines: 5, arms: 3, depth: 1}),
+        assert!(visit.matches.contains(&BlockJson::Match{ source: main_rs(92, 96), def_id: match_json.clone(), lines: 5, arms: 3, depth: 1,
+            scrutinee: "Int".to_string(), arm_patterns: vec![ArmJson::default(), ArmJson::default(), ArmJson { wildcard: true, ..ArmJson::default() }],
+            all_variants_listed: None }),
             "Match block not found in {:?}", visit.matches);
 
         let match_point = BlockJson::Def { 
+            source: main_rs(108, 115),
             params: serde_json::to_value(Params {
                 closure_traits: Vec::new(),
                 ty_kinds: vec![(false, "Adt".to_string())]
@@ -239,12 +290,14 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         };
 
         let pt_json = compare_fn("match_point", &match_point, &visit.fns);
-        assert!(visit.loops.contains(&BlockJson::Loop{ def_id: pt_json.clone(), lines: 6, depth: 1}),
+        assert!(visit.loops.contains(&plain_loop(main_rs(109, 114), &pt_json, 6, 1, "For", Some("Range"))),
             "Loop block not found in {:?}", visit.loops);
-        assert!(visit.matches.contains(&BlockJson::Match{ def_id: pt_json, lines: 4, arms: 2, depth: 2}),
+        assert!(visit.matches.contains(&BlockJson::Match{ source: main_rs(110, 113), def_id: pt_json, lines: 4, arms: 2, depth: 2,
+            scrutinee: "Bool".to_string(), arm_patterns: vec![ArmJson::default(); 2], all_variants_listed: None }),
             "Match block not found in {:?}", visit.matches);
 
         let iflet = BlockJson::Def {
+            source: main_rs(99, 104),
             params: serde_json::to_value(Params::default()).unwrap(),
             ret: serde_json::to_value(Return::default()).unwrap(),
             unsafety: false,
@@ -253,10 +306,20 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         };
 
         let iflet_json = compare_fn("iflet", &iflet, &visit.fns);
-        assert!(visit.let_exprs.contains(&BlockJson::LetExpr { def_id: iflet_json.clone(), depth: 1 }),
+        let let_expr = |source, def_id: &String, depth, kind: &str, chained, scrutinee: &str, has_else| BlockJson::LetExpr {
+            source,
+            def_id: def_id.clone(),
+            depth,
+            kind: kind.to_string(),
+            chained,
+            scrutinee: scrutinee.to_string(),
+            has_else,
+        };
+        assert!(visit.let_exprs.contains(&let_expr(main_rs(101, 101), &iflet_json, 1, "IfLet", false, "Option", false)),
             "Match block not found in {:?}", visit.let_exprs);
 
         let factorial = BlockJson::Def {
+            source: main_rs(121, 127),
             params: serde_json::to_value(Params {
                 closure_traits: Vec::new(),
                 ty_kinds: vec![(false, "Uint".to_string())]
@@ -274,6 +337,7 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         compare_fn("factorial", &factorial, &visit.fns);
 
         let input_math = BlockJson::Def {
+            source: main_rs(130, 132),
             params: serde_json::to_value(Params {
                 closure_traits: Vec::new(),
                 ty_kinds: vec![(false, "Adt".to_string())]
@@ -287,6 +351,7 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         compare_fn("input_math", &input_math, &visit.fns);
 
         let equal_vecs = BlockJson::Def {
+            source: main_rs(135, 149),
             params: serde_json::to_value(Params::default()).unwrap(),
             ret: serde_json::to_value(Return::default()).unwrap(),
             unsafety: false,
@@ -294,12 +359,34 @@ const PATH: &str = "src/plugin/tests/unit_tests";
             lines: 15,
         };
 
+        // each iterator chain, from where its iterator comes from to what consumes it
+        let pipeline = |source, def_id: &String, depth, origin: (&str, Option<&str>), methods: &[&str], terminal: Option<&str>, collect_type: Option<&str>| {
+            let methods: Vec<String> = methods.iter().map(|m| m.to_string()).collect();
+            let adapters = methods[..methods.len() - terminal.is_some_and(|t| t != "for") as usize].to_vec();
+            BlockJson::Iter {
+                source,
+                def_id: def_id.clone(),
+                depth,
+                methods,
+                origin: origin.0.to_string(),
+                origin_method: origin.1.map(str::to_string),
+                adapters,
+                terminal: terminal.map(str::to_string),
+                collect_type: collect_type.map(str::to_string),
+            }
+        };
+        let assert_pipeline = |pipeline: BlockJson| {
+            assert!(visit.iter_mthds.contains(&pipeline), "Iter block {:?} not found in {:?}", pipeline, visit.iter_mthds);
+        };
+
         let ev_json = compare_fn("equal_vecs", &equal_vecs, &visit.fns);
-        assert!(visit.iter_mthds.contains(&BlockJson::Iter { def_id: ev_json.clone(), depth: 1,
-            methods: vec!["eq".to_string(), "skip".to_string(), "skip".to_string(), "sum".to_string()]}),
-            "Iter block not found in {:?}", visit.iter_mthds);
+        assert_pipeline(pipeline(main_rs(139, 144), &ev_json, 1, ("IntoIterator", Some("iter")), &["skip", "eq"], Some("eq"), None));
+        // passed to `eq` rather than consumed in its own chain
+        assert_pipeline(pipeline(main_rs(142, 144), &ev_json, 1, ("IntoIterator", Some("iter")), &["skip"], None, None));
+        assert_pipeline(pipeline(main_rs(146, 146), &ev_json, 1, ("IntoIterator", Some("iter")), &["sum"], Some("sum"), None));
 
         let nested_ifs = BlockJson::Def {
+            source: main_rs(151, 173),
             params: serde_json::to_value(Params {
                 closure_traits: Vec::new(),
                 ty_kinds: vec![(false, "Bool".to_string()), (false, "Bool".to_string())]
@@ -311,11 +398,12 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         };
 
         let nested_json = compare_fn("nested_ifs", &nested_ifs, &visit.fns);
-        assert!(visit.let_exprs.contains(&BlockJson::LetExpr { def_id: nested_json.clone(), depth: 3 }));
-        assert!(visit.loops.contains(&BlockJson::Loop{ def_id: nested_json.clone(), lines: 6, depth: 2}));
-        assert!(visit.iter_mthds.contains(&BlockJson::Iter { def_id: nested_json.clone(), depth: 3, methods: vec!["for_each".to_string()]}));
+        assert!(visit.let_exprs.contains(&let_expr(main_rs(159, 159), &nested_json, 3, "IfLet", false, "Option", false)));
+        assert!(visit.loops.contains(&jumps(plain_loop(main_rs(166, 171), &nested_json, 6, 2, "Loop", None), false, 1, 0, false)));
+        assert_pipeline(pipeline(main_rs(154, 156), &nested_json, 3, ("Range", None), &["for_each"], Some("for_each"), None));
 
-        let sample_empty = BlockJson::Def {
+        let sample_empty = |source| BlockJson::Def {
+            source,
             params: serde_json::to_value(Params::default()).unwrap(),
             ret: serde_json::to_value(Return::default()).unwrap(),
             unsafety: false,
@@ -323,12 +411,13 @@ const PATH: &str = "src/plugin/tests/unit_tests";
             lines: 1,
         };
         
-        compare_fn("mod_test", &sample_empty, &visit.fns);
-        compare_fn("other_test", &sample_empty, &visit.fns);
-        compare_fn("const_function", &sample_empty, &visit.fns);
-        compare_fn("trait_fn", &sample_empty, &visit.fns);
+        compare_fn("mod_test", &sample_empty(main_rs(178, 178)), &visit.fns);
+        compare_fn("other_test", &sample_empty(other_rs(1, 1)), &visit.fns);
+        compare_fn("const_function", &sample_empty(other_rs(3, 3)), &visit.fns);
+        compare_fn("trait_fn", &sample_empty(other_rs(14, 14)), &visit.fns);
 
         let async_fn = BlockJson::Def {
+            source: other_rs(5, 5),
             params: serde_json::to_value(Params::default()).unwrap(),
             ret: serde_json::to_value(Return {
                 mutabl: false,
@@ -343,6 +432,177 @@ const PATH: &str = "src/plugin/tests/unit_tests";
         
         compare_fn("async_function", &async_fn, &visit.fns);
 
+        let capture_styles = BlockJson::Def {
+            source: main_rs(182, 191),
+            params: serde_json::to_value(Params::default()).unwrap(),
+            ret: serde_json::to_value(Return::default()).unwrap(),
+            unsafety: false,
+            recursive: false,
+            lines: 10,
+        };
+
+        let capture_json = compare_fn("capture_styles", &capture_styles, &visit.fns);
+        for (line, captures, is_move) in [(185, vec![("count", "ByRefMut")], false), (187, vec![("name", "ByRefShared")], false), (189, vec![("name", "ByValue")], true)] {
+            let closure = BlockJson::Closure {
+                source: main_rs(line, line),
+                def_id: capture_json.clone(),
+                lines: 1,
+                depth: 1,
+                is_move,
+                captures: captures.iter().map(|(place, kind)| (place.to_string(), kind.to_string())).collect(),
+                escapes: None,
+            };
+            assert!(visit.closures.contains(&closure), "Closure {:?} not found in {:?}", closure, visit.closures);
+        }
+
+        let make_adder = BlockJson::Def {
+            source: main_rs(193, 195),
+            params: serde_json::to_value(Params {
+                closure_traits: Vec::new(),
+                ty_kinds: vec![(false, "Uint".to_string())]
+            }).unwrap(),
+            ret: serde_json::to_value(Return {
+                mutabl: false,
+                closure_trait: Some("Fn".to_string()),
+                ty_kind: "Closure".to_string()
+            }).unwrap(),
+            unsafety: false,
+            recursive: false,
+            lines: 3,
+        };
+
+        let adder_json = compare_fn("make_adder", &make_adder, &visit.fns);
+        assert!(visit.closures.contains(&BlockJson::Closure { source: main_rs(194, 194), def_id: adder_json, lines: 1, depth: 1, is_move: true,
+            captures: vec![("n".to_string(), "ByValue".to_string())], escapes: Some("Return".to_string()) }),
+            "Returned closure not found in {:?}", visit.closures);
+
+        let spawn_worker = BlockJson::Def {
+            source: main_rs(197, 200),
+            params: serde_json::to_value(Params {
+                closure_traits: Vec::new(),
+                ty_kinds: vec![(false, "Adt".to_string())]
+            }).unwrap(),
+            ret: serde_json::to_value(Return::default()).unwrap(),
+            unsafety: false,
+            recursive: false,
+            lines: 4,
+        };
+
+        let spawn_json = compare_fn("spawn_worker", &spawn_worker, &visit.fns);
+        assert!(visit.closures.contains(&BlockJson::Closure { source: main_rs(198, 198), def_id: spawn_json, lines: 1, depth: 1, is_move: true,
+            captures: vec![("v".to_string(), "ByValue".to_string())], escapes: Some("Spawn".to_string()) }),
+            "Spawned closure not found in {:?}", visit.closures);
+
+        // escaping is read from the callee's signature, not its name
+        let escapes = |line: usize| visit.closures.iter().find_map(|closure| match closure {
+            BlockJson::Closure { source, escapes, .. } if source.line_start == line => Some(escapes.clone()),
+            _ => None,
+        });
+        assert_eq!(escapes(363), Some(Some("Spawn".to_string())));
+        assert_eq!(escapes(364), Some(Some("Spawn".to_string())));
+        assert_eq!(escapes(365), Some(None));
+
+        let pipelines = BlockJson::Def {
+            source: main_rs(213, 222),
+            params: serde_json::to_value(Params {
+                closure_traits: Vec::new(),
+                ty_kinds: vec![(false, "Adt".to_string())]
+            }).unwrap(),
+            ret: serde_json::to_value(Return { mutabl: false, closure_trait: None, ty_kind: "Adt".to_string() }).unwrap(),
+            unsafety: false,
+            recursive: false,
+            lines: 10,
+        };
+
+        let pipelines_json = compare_fn("pipelines", &pipelines, &visit.fns);
+        assert_pipeline(pipeline(main_rs(214, 214), &pipelines_json, 1, ("Range", None), &["filter", "sum"], Some("sum"), None));
+        assert_pipeline(pipeline(main_rs(215, 215), &pipelines_json, 1, ("Custom", None), &[], Some("for"), None));
+        assert_pipeline(pipeline(main_rs(218, 218), &pipelines_json, 1, ("IntoIterator", None), &[], Some("for"), None));
+        assert_pipeline(pipeline(main_rs(221, 221), &pipelines_json, 1, ("IntoIterator", Some("iter")), &["map", "collect"], Some("collect"), Some("std::vec::Vec")));
+
+        let loop_kinds = BlockJson::Def {
+            source: main_rs(225, 255),
+            params: serde_json::to_value(Params {
+                closure_traits: Vec::new(),
+                ty_kinds: vec![(true, "Adt".to_string()), (true, "Adt".to_string())]
+            }).unwrap(),
+            ret: serde_json::to_value(Return { mutabl: false, closure_trait: None, ty_kind: "Uint".to_string() }).unwrap(),
+            unsafety: false,
+            recursive: false,
+            lines: 31,
+        };
+
+        let kinds_json = compare_fn("loop_kinds", &loop_kinds, &visit.fns);
+        let assert_loop = |expected: BlockJson| {
+            assert!(visit.loops.contains(&expected), "Loop block {:?} not found in {:?}", expected, visit.loops);
+        };
+        // the labeled loop counts the `break` and `continue` of the loop nested in it
+        assert_loop(jumps(plain_loop(main_rs(226, 235), &kinds_json, 10, 1, "For", Some("Ref")), true, 1, 1, false));
+        assert_loop(plain_loop(main_rs(227, 234), &kinds_json, 8, 2, "For", Some("Range")));
+        assert_loop(plain_loop(main_rs(236, 238), &kinds_json, 3, 1, "For", Some("RefMut")));
+        assert_loop(jumps(plain_loop(main_rs(239, 243), &kinds_json, 5, 1, "WhileLet", None), false, 0, 1, false));
+        assert_loop(jumps(plain_loop(main_rs(245, 250), &kinds_json, 6, 1, "Loop", None), false, 1, 0, true));
+        assert_loop(plain_loop(main_rs(251, 253), &kinds_json, 3, 1, "For", Some("Value")));
+
+        let match_features = BlockJson::Def {
+            source: main_rs(299, 319),
+            params: serde_json::to_value(Params {
+                closure_traits: Vec::new(),
+                ty_kinds: vec![(false, "Ref".to_string()), (false, "Uint".to_string()), (false, "Tuple".to_string()), (false, "Ref".to_string())]
+            }).unwrap(),
+            ret: serde_json::to_value(Return { mutabl: false, closure_trait: None, ty_kind: "Uint".to_string() }).unwrap(),
+            unsafety: false,
+            recursive: false,
+            lines: 21,
+        };
+
+        let features_json = compare_fn("match_features", &match_features, &visit.fns);
+        let arm = ArmJson::default;
+        let bound = ArmJson { bindings: 1, ..arm() };
+        for (lines, arms, scrutinee, arm_patterns, all_variants_listed) in [
+            // the first arm's guard leaves `Circle` to the second, which still names it
+            ((300, 305), 4, "Enum", vec![ArmJson { guard: true, ..bound.clone() }, bound.clone(), bound.clone(), arm()], Some(true)),
+            ((306, 310), 3, "Int", vec![ArmJson { range: true, ..arm() }, ArmJson { range: true, or_pattern: true, ..arm() }, ArmJson { wildcard: true, ..arm() }], None),
+            ((311, 314), 2, "Tuple", vec![arm(), bound.clone()], None),
+            ((315, 318), 2, "String", vec![arm(), bound.clone()], None),
+        ] {
+            let expected = BlockJson::Match {
+                source: main_rs(lines.0, lines.1),
+                def_id: features_json.clone(),
+                lines: lines.1 - lines.0 + 1,
+                arms,
+                depth: 1,
+                scrutinee: scrutinee.to_string(),
+                arm_patterns,
+                all_variants_listed,
+            };
+            assert!(visit.matches.contains(&expected), "Match block {:?} not found in {:?}", expected, visit.matches);
+        }
+
+        let let_forms = BlockJson::Def {
+            source: main_rs(322, 340),
+            params: serde_json::to_value(Params {
+                closure_traits: Vec::new(),
+                ty_kinds: vec![(false, "Adt".to_string()), (false, "Adt".to_string()), (false, "Ref".to_string())]
+            }).unwrap(),
+            ret: serde_json::to_value(Return { mutabl: false, closure_trait: None, ty_kind: "Uint".to_string() }).unwrap(),
+            unsafety: false,
+            recursive: false,
+            lines: 19,
+        };
+
+        let forms_json = compare_fn("let_forms", &let_forms, &visit.fns);
+        for expected in [
+            let_expr(main_rs(323, 325), &forms_json, 1, "LetElse", false, "Option", true),
+            let_expr(main_rs(327, 327), &forms_json, 1, "IfLet", false, "Result", true),
+            let_expr(main_rs(332, 332), &forms_json, 1, "IfLet", true, "Enum", false),
+            let_expr(main_rs(332, 332), &forms_json, 1, "IfLet", true, "Option", false),
+            // the `else { break }` the loop is lowered with isn't counted as an `else`
+            let_expr(main_rs(336, 336), &forms_json, 2, "WhileLet", true, "Option", false),
+        ] {
+            assert!(visit.let_exprs.contains(&expected), "LetExpr {:?} not found in {:?}", expected, visit.let_exprs);
+        }
+
 
         // let tslang = BlockJson::NoType { 
         //     def_id: "DefId(0:13 ~ unit_tests[a66e]::other::TSLanguage)".to_string(),
@@ -352,4 +612,736 @@ const PATH: &str = "src/plugin/tests/unit_tests";
 
         // compare_fn("TSLanguage", &tslang, &visit.fns);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn borrowck() {
+        let (status, parsed_jsons) = salt_output(BORROWCK_PATH, &["borrowck"]);
+        assert!(!status.success(), "Borrowck test crate should not compile");
+        assert!(parsed_jsons.len() == 1, "Expected 1 JSON object, got {}: {:?}", parsed_jsons.len(), parsed_jsons);
+
+        let result: BorrowckResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        println!("{}", serde_json::to_string_pretty(&result.errors).unwrap());
+        assert!(result.errors.len() == 5, "Expected 5 borrow errors, got {:?}", result.errors);
+
+        let find = |place: &str, access: &str| result.errors.iter()
+            .find(|e| e.borrowed_place == place && e.access_kind == access)
+            .unwrap_or_else(|| panic!("No {} error on {} in {:?}", access, place, result.errors));
+
+        let push = find("v", "MutBorrow");
+        assert_eq!((push.borrow_kind.as_str(), push.borrow_span.line_start, push.access_span.line_start), ("Shared", 8, 9));
+        assert_eq!(push.later_use_span.as_ref().unwrap().line_start, 10);
+        assert_eq!(push.region_lines, (8, 10));
+
+        let two_mut = find("s", "MutBorrow");
+        assert_eq!((two_mut.borrow_kind.as_str(), two_mut.borrow_span.line_start, two_mut.access_span.line_start), ("Mut", 16, 17));
+        assert_eq!(two_mut.later_use_span.as_ref().unwrap().line_start, 18);
+
+        let moved = find("s", "Move");
+        assert_eq!((moved.borrow_span.line_start, moved.access_span.line_start), (25, 26));
+        assert_eq!(moved.later_use_span.as_ref().unwrap().line_start, 27);
+
+        let dropped = find("x", "Drop");
+        assert_eq!((dropped.borrow_span.line_start, dropped.access_span.line_start), (35, 36));
+        assert_eq!(dropped.later_use_span.as_ref().unwrap().line_start, 37);
+
+        // the errors are the ones rustc reports, so NLL's problem case #3 is one too
+        let returned = find("*v", "MutBorrow");
+        assert_eq!((returned.borrow_kind.as_str(), returned.borrow_span.line_start, returned.access_span.line_start), ("Shared", 65, 68));
+    }
+
+    #[test]
+    fn borrows() {
+        let (_, parsed_jsons) = salt_output(BORROWCK_PATH, &["borrows", "--fn", "no_errors"]);
+        assert!(parsed_jsons.len() == 1, "Expected 1 JSON object, got {}: {:?}", parsed_jsons.len(), parsed_jsons);
+
+        let result: BorrowsResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        println!("{}", serde_json::to_string_pretty(&result.loans).unwrap());
+        assert!(result.loans.len() == 2, "Expected 2 loans, got {:?}", result.loans);
+
+        let mut_loan = &result.loans[0];
+        assert_eq!((mut_loan.borrowed_place.as_str(), mut_loan.mutable), ("x", true));
+        assert_eq!((mut_loan.span.line_start, mut_loan.span.column_start), (42, 13));
+        assert_eq!(mut_loan.live_lines, vec![42, 43]);
+
+        let print_loan = &result.loans[1];
+        assert_eq!((print_loan.borrowed_place.as_str(), print_loan.mutable), ("x", false));
+        assert_eq!(print_loan.live_lines, vec![44]);
+
+        // `new` could be either constructor, so neither is picked
+        let (_, parsed_jsons) = salt_output(BORROWCK_PATH, &["borrows", "--fn", "new"]);
+        assert!(parsed_jsons.is_empty(), "Expected no JSON object, got {:?}", parsed_jsons);
+        let stderr = salt_stderr(BORROWCK_PATH, &["borrows", "--fn", "new"]);
+        assert!(stderr.contains("`new` matches more than one function"), "{}", stderr);
+        assert!(stderr.contains("Counter::new") && stderr.contains("Meter::new"), "{}", stderr);
+        let (_, parsed_jsons) = salt_output(BORROWCK_PATH, &["borrows", "--fn", "Meter::new"]);
+        assert!(parsed_jsons.len() == 1, "Expected 1 JSON object, got {}: {:?}", parsed_jsons.len(), parsed_jsons);
+    }
+
+    #[test]
+    fn moves() {
+        let (status, parsed_jsons) = salt_output(MOVES_PATH, &["moves"]);
+        assert!(!status.success(), "Moves test crate should not compile");
+        assert!(parsed_jsons.len() == 1, "Expected 1 JSON object, got {}: {:?}", parsed_jsons.len(), parsed_jsons);
+
+        let result: MovesResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        println!("{}", serde_json::to_string_pretty(&result.locals).unwrap());
+        assert!(result.locals.len() == 6, "Expected 6 moved locals, got {:?}", result.locals);
+
+        let find = |line: usize| result.locals.iter()
+            .find(|l| l.moves.iter().any(|m| m.span.line_start == line))
+            .unwrap_or_else(|| panic!("No move on line {} in {:?}", line, result.locals));
+
+        for (move_line, kind, use_line) in [(10, "CallArgument", 11), (17, "ForLoop", 18), (24, "ClosureCapture", 25), (33, "Assignment", 35)] {
+            let local = find(move_line);
+            assert!(!local.is_copy);
+            assert_eq!(local.moves.len(), 1);
+            assert_eq!(local.moves[0].kind, kind);
+            assert_eq!(local.uses_after_move.len(), 1, "Expected 1 use after move in {:?}", local);
+            assert_eq!(local.uses_after_move[0].span.line_start, use_line);
+            assert_eq!(local.uses_after_move[0].move_outs, vec![local.moves[0].move_out]);
+        }
+
+        let reassigned = find(41);
+        assert!(reassigned.uses_after_move.is_empty(), "Reinitialized local used after move: {:?}", reassigned);
+
+        let pair = find(53);
+        assert_eq!(pair.local, "p");
+        assert_eq!(pair.moves[0].place, "p.a");
+        assert_eq!(pair.uses_after_move.len(), 1, "Only the whole pair is used after a partial move: {:?}", pair);
+        assert_eq!((pair.uses_after_move[0].place.as_str(), pair.uses_after_move[0].span.line_start), ("p", 55));
+        assert_eq!(pair.uses_after_move[0].move_outs, vec![pair.moves[0].move_out]);
+    }
+
+    #[test]
+    fn drops() {
+        let (status, parsed_jsons) = salt_output(DROPS_PATH, &["drops"]);
+        assert!(!status.success(), "Drops test crate should not compile");
+        assert!(parsed_jsons.len() == 1, "Expected 1 JSON object, got {}: {:?}", parsed_jsons.len(), parsed_jsons);
+
+        let result: DropsResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        println!("{}", serde_json::to_string_pretty(&result.bodies).unwrap());
+        assert!(result.bodies.len() == 2, "Expected 2 bodies with borrows outliving their local, got {:?}", result.bodies);
+
+        let outstanding = result.bodies.iter()
+            .flat_map(|body| &body.locals)
+            .filter(|local| !local.outstanding_borrows.is_empty())
+            .collect::<Vec<_>>();
+        assert!(outstanding.len() == 2, "Expected 2 locals dropped while borrowed, got {:?}", outstanding);
+
+        // E0597
+        let x = outstanding.iter().find(|local| local.local == "x").unwrap();
+        assert!(!x.is_temporary);
+        assert_eq!((x.storage_dead[0].line_start, x.scope_end_line), (11, 11));
+        let borrow = &x.outstanding_borrows[0];
+        assert_eq!((borrow.borrow_span.line_start, borrow.dropped_span.line_start), (10, 11));
+        assert_eq!(borrow.needed_span.as_ref().unwrap().line_start, 12);
+
+        // E0716
+        let tmp = outstanding.iter().find(|local| local.is_temporary).unwrap();
+        assert_eq!(tmp.local, "String::from(\"hi\")");
+        assert_eq!((tmp.drops[0].line_start, tmp.scope_end_line), (18, 18));
+        let borrow = &tmp.outstanding_borrows[0];
+        assert_eq!((borrow.borrow_span.line_start, borrow.dropped_span.line_start), (18, 18));
+        assert_eq!(borrow.needed_span.as_ref().unwrap().line_start, 19);
+
+        let (_, parsed_jsons) = salt_output(DROPS_PATH, &["drops", "--fn", "extended_temporary"]);
+        let result: DropsResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        assert!(result.bodies.len() == 1, "Expected only extended_temporary, got {:?}", result.bodies);
+        let body = &result.bodies[0];
+        assert_eq!(body.locals.iter().map(|local| local.local.as_str()).collect::<Vec<_>>(), vec!["r", "n"]);
+        assert!(body.extended_temporaries.len() == 1, "Only `&String::from` is extended: {:?}", body.extended_temporaries);
+        let extended = &body.extended_temporaries[0];
+        assert_eq!((extended.decl_span.line_start, extended.storage_dead.line_start), (23, 26));
+    }
+
+    #[test]
+    fn fixes() {
+        let (status, parsed_jsons) = salt_output(FIXES_PATH, &["fixes"]);
+        assert!(!status.success(), "Fixes test crate should not compile");
+        assert!(parsed_jsons.len() == 1, "Expected 1 JSON object, got {}: {:?}", parsed_jsons.len(), parsed_jsons);
+
+        let result: FixesResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        println!("{}", serde_json::to_string_pretty(&result.fixes).unwrap());
+        let codes = result.fixes.iter().map(|fix| fix.code.as_str()).collect::<Vec<_>>();
+        assert_eq!(codes, vec!["E0502", "E0499", "E0505", "E0382", "E0502", "E0382"]);
+
+        let kinds = |code: &str| result.fixes.iter()
+            .find(|fix| fix.code == code).unwrap()
+            .candidates.iter().map(|candidate| candidate.kind.as_str()).collect::<Vec<_>>();
+        // narrowing the borrow's scope never compiles here, since the borrow is used after the access
+        assert_eq!(kinds("E0502"), vec!["ReorderUse"]);
+        assert_eq!(kinds("E0499"), vec!["ReorderUse"]);
+        // `&mut s` would need `s` to be mutable, and isn't allowed while `r` is live anyway
+        assert_eq!(kinds("E0505"), vec!["Clone", "Borrow", "Clone"]);
+        // `consume` takes a `String`, so only cloning or printing first fixes the use after move
+        assert_eq!(kinds("E0382"), vec!["Clone", "ReorderUse"]);
+
+        let reorder = &result.fixes[0].candidates[0];
+        assert_eq!(reorder.edits[0].replacement, "    println!(\"{}\", first);\n");
+        assert_eq!((reorder.edits[0].span.line_start, reorder.edits[1].span.line_start, reorder.edits[1].span.line_end), (9, 10, 11));
+
+        let clone = &result.fixes[3].candidates[0];
+        assert_eq!(clone.edits[0].replacement, "s.clone()");
+        assert_eq!((clone.edits[0].span.line_start, clone.edits[0].span.column_start), (35, 13));
+
+        // a candidate only has to fix its own error, the body's other error can remain
+        let kinds_at = |idx: usize| result.fixes[idx].candidates.iter().map(|candidate| candidate.kind.as_str()).collect::<Vec<_>>();
+        assert_eq!(kinds_at(4), vec!["ReorderUse"]);
+        assert_eq!(kinds_at(5), vec!["Clone", "ReorderUse"]);
+    }
+
+    #[test]
+    fn mismatches() {
+        let (status, parsed_jsons) = salt_output(MISMATCHES_PATH, &["mismatches"]);
+        assert!(!status.success(), "Mismatches test crate should not compile");
+        assert!(parsed_jsons.len() == 1, "Expected 1 JSON object, got {}: {:?}", parsed_jsons.len(), parsed_jsons);
+
+        let result: MismatchesResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        println!("{}", serde_json::to_string_pretty(&result.mismatches).unwrap());
+        let keys = result.mismatches.keys().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(keys, vec![
+            "src/main.rs:12:15", "src/main.rs:17:18", "src/main.rs:23:18",
+            "src/main.rs:28:18", "src/main.rs:33:30", "src/main.rs:38:5", "src/main.rs:53:5",
+            "src/main.rs:74:21",
+        ]);
+
+        let coercions = |key: &str| result.mismatches[key].coercions.iter().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(coercions("src/main.rs:12:15"), vec!["&", ".as_ref()"]);
+        assert_eq!(coercions("src/main.rs:17:18"), vec![".into()", ".to_string()"]);
+        assert_eq!(coercions("src/main.rs:23:18"), vec!["*"]);
+        assert_eq!(coercions("src/main.rs:28:18"), vec![".into()"]);
+        assert_eq!(coercions("src/main.rs:33:30"), vec![".as_ref()"]);
+
+        let owned = &result.mismatches["src/main.rs:12:15"];
+        assert_eq!((owned.context.as_str(), owned.expected.as_str(), owned.found.as_str()), ("Argument", "&str", "std::string::String"));
+        assert_eq!(owned.autoderef_steps, vec!["std::string::String", "str"]);
+
+        let boxed = &result.mismatches["src/main.rs:23:18"];
+        assert_eq!(boxed.deref_count, Some(1));
+        assert_eq!(result.mismatches["src/main.rs:38:5"].context, "Return");
+
+        // the signature's `&'a str` matches the `&str` reached through `Deref`
+        let named = &result.mismatches["src/main.rs:53:5"];
+        assert_eq!((named.expected.as_str(), named.found.as_str()), ("&str", "Name<'_>"));
+        assert_eq!(named.autoderef_steps, vec!["Name<'_>", "&str", "str"]);
+        assert_eq!(named.deref_count, Some(1));
+        assert_eq!(coercions("src/main.rs:53:5"), vec!["&", "*"]);
+
+        // unsizing, fn pointers and raw pointers are coercions rustc accepts, next to a real mismatch
+        let coerced = &result.mismatches["src/main.rs:74:21"];
+        assert_eq!((coerced.expected.as_str(), coerced.found.as_str()), ("std::string::String", "i32"));
+        assert_eq!(coercions("src/main.rs:74:21"), vec![".to_string()"]);
+    }
+
+    #[test]
+    fn query() {
+        let states = |at: &str| {
+            let (status, parsed_jsons) = salt_output(QUERY_PATH, &["query", "--at", at]);
+            assert!(status.success(), "Query test crate should compile");
+            assert!(parsed_jsons.len() == 1, "Expected 1 JSON object, got {}: {:?}", parsed_jsons.len(), parsed_jsons);
+            let result: QueryResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+            println!("{}", serde_json::to_string_pretty(&result.ownership).unwrap());
+            result.ownership
+        };
+
+        // right after `consume(s)`, while `first` still borrows `v`
+        let ownership = states("src/main.rs:18:5");
+        assert_eq!(ownership.location.line_start, 18);
+        let locals = ownership.locals.iter()
+            .map(|local| (local.local.as_str(), local.state.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(locals, vec![
+            ("p", "Initialized"), ("v", "Initialized"), ("first", "Initialized"),
+            ("s", "Moved"), ("later", "Uninitialized"),
+        ]);
+        let v = &ownership.locals[1];
+        assert_eq!(v.ty, "std::vec::Vec<i32>");
+        assert_eq!(v.loans.len(), 1);
+        assert_eq!((v.loans[0].borrow_kind.as_str(), v.loans[0].mutable, v.loans[0].span.line_start), ("Shared", false, 14));
+
+        // after `first`'s last use and the move out of `p.a`
+        let ownership = states("src/main.rs:21:5");
+        let state = |name: &str| ownership.locals.iter().find(|local| local.local == name).unwrap().state.clone();
+        assert_eq!(state("p"), "PartiallyMoved");
+        assert_eq!(state("later"), "Initialized");
+        assert!(ownership.locals.iter().all(|local| local.loans.is_empty()));
+
+        // file names match by whole path components, so `ain.rs` isn't `main.rs`
+        let (status, parsed_jsons) = salt_output(QUERY_PATH, &["query", "--at", "ain.rs:18:5"]);
+        assert!(status.success());
+        assert!(parsed_jsons.is_empty(), "Expected no result, got {:?}", parsed_jsons);
+        assert_eq!(states("main.rs:18:5").location.line_start, 18);
+    }
+
+    #[test]
+    fn liveness() {
+        let (status, parsed_jsons) = salt_output(LIVENESS_PATH, &["liveness"]);
+        assert!(!status.success(), "Liveness test crate should not compile");
+        assert!(parsed_jsons.len() == 1, "Expected 1 JSON object, got {}: {:?}", parsed_jsons.len(), parsed_jsons);
+
+        let result: LivenessResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        println!("{}", serde_json::to_string_pretty(&result.chains).unwrap());
+        assert_eq!(result.chains.len(), 3);
+        let steps = |chain: &LivenessJson| chain.steps.iter()
+            .map(|step| (step.kind.clone(), step.variable.clone().unwrap_or_default(), step.span.line_start))
+            .collect::<Vec<_>>();
+        let step = |kind: &str, variable: &str, line: usize| (kind.to_string(), variable.to_string(), line);
+
+        // `first` flows into `alias`, which is printed after the push
+        assert_eq!(result.chains[0].conflict_span.as_ref().unwrap().line_start, 10);
+        assert_eq!(steps(&result.chains[0]), vec![
+            step("Borrow", "v", 8), step("FlowsInto", "first", 8), step("FlowsInto", "alias", 9), step("Use", "alias", 11),
+        ]);
+        assert_eq!(steps(&result.chains[1]), vec![
+            step("Borrow", "s", 21), step("FlowsInto", "h", 21), step("Use", "h", 23),
+        ]);
+        assert_eq!(steps(&result.chains[2]), vec![
+            step("Borrow", "v", 33), step("FlowsInto", "f", 33), step("Use", "f", 35),
+        ]);
+
+        // a returned reborrow is kept alive by the signature, not a use
+        let (_, parsed_jsons) = salt_output(LIVENESS_PATH, &["liveness", "--at", "src/main.rs:40:10"]);
+        let result: LivenessResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        assert_eq!(result.chains.len(), 1);
+        assert!(result.chains[0].conflict_span.is_none());
+        assert_eq!(steps(&result.chains[0]), vec![step("Borrow", "*v", 40), step("OutlivesFunction", "", 39)]);
+    }
+
+    #[test]
+    fn errors() {
+        let (status, parsed_jsons) = salt_output(MISMATCHES_PATH, &[]);
+        assert!(!status.success(), "Mismatches test crate should not compile");
+        assert!(parsed_jsons.len() == 1, "Expected 1 JSON object, got {}: {:?}", parsed_jsons.len(), parsed_jsons);
+
+        let result: PrintResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        println!("{}", serde_json::to_string_pretty(&result.errors).unwrap());
+        assert!(result.partial);
+        assert_eq!(result.errors.len(), 8);
+        assert!(result.errors.iter().all(|error| error.code.as_deref() == Some("E0308") && error.level == "Error"));
+        let lines = result.errors.iter().map(|error| error.span.line_start).collect::<Vec<_>>();
+        assert_eq!(lines, vec![12, 17, 23, 28, 33, 38, 53, 74]);
+
+        // errors are attributed to the same function hashes as the other reports
+        let (_, parsed_jsons) = salt_output(MISMATCHES_PATH, &["mismatches"]);
+        let mismatches: MismatchesResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        for error in &result.errors {
+            let key = format!("{}:{}:{}", error.span.file_name, error.span.line_start, error.span.column_start);
+            if let Some(mismatch) = mismatches.mismatches.get(&key) {
+                assert_eq!(error.def_id.as_ref(), Some(&mismatch.def_id));
+            }
+        }
+
+        // the functions that type-checked are still analysed, the ones with errors are only listed
+        let fns = result.visit_res["fns"].as_object().unwrap();
+        assert_eq!(fns.len(), 6);
+        let no_type = result.visit_res["no_type"].as_array().unwrap();
+        let failed = no_type
+            .iter()
+            .filter(|block| block["NoType"]["type_error"] == true)
+            .map(|block| block["NoType"]["def_id"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(failed.len(), 8);
+        for error in &result.errors {
+            assert!(failed.contains(&error.def_id.as_deref().unwrap()));
+            assert!(!fns.contains_key(error.def_id.as_deref().unwrap()));
+        }
+
+        // capturing the errors keeps the rendering options rustc was given
+        let stderr = salt_stderr(MISMATCHES_PATH, &["--", "--config", "build.rustflags=[\"-Ztrack-diagnostics\"]"]);
+        assert_eq!(stderr.matches("-Ztrack-diagnostics: created at").count(), 8, "{}", stderr);
+    }
+    #[test]
+    fn anonymize() {
+        // the same truncated SHA-256 the extension's hashString computes
+        assert_eq!(hash_file_name("/home/student/project/src/main.rs"), "bea8be80");
+
+        // without a participant key, crate ids fall back to unkeyed SHA-256
+        let (_, parsed_jsons) = salt_output(PATH, &[]);
+        let result: PrintResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        assert_eq!(result.krate.crate_id, "e0a22a9269cc6c85");
+
+        // ids don't depend on the session, so two runs can be joined
+        let (_, rerun) = salt_output(PATH, &[]);
+        assert_eq!(parsed_jsons[0]["visit_res"]["fns"], rerun[0]["visit_res"]["fns"]);
+    }
+    #[test]
+    fn identifiers() {
+        let main_rs = std::fs::canonicalize(format!("{PATH}/src/main.rs")).unwrap().display().to_string();
+        let match_source = |args: &[&str]| {
+            let (_, parsed_jsons) = salt_output(PATH, args);
+            let result: PrintResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+            let visit: VisitorJson = serde_json::from_value(result.visit_res).unwrap();
+            let BlockJson::Match { def_id, source, .. } = visit.matches[0].clone() else {
+                panic!("Expected a match, got {:?}", visit.matches)
+            };
+            let factorial = visit.fns.values().find_map(|block| match block {
+                BlockJson::Def { source, .. } if source.line_start == 121 => Some(source.clone()),
+                _ => None,
+            });
+            (result.krate.crate_id, def_id, source, factorial.unwrap())
+        };
+
+        // hashes only, the file hashed as the extension hashes file names
+        let (crate_id, def_id, source, factorial) = match_source(&[]);
+        assert_eq!(crate_id, "e0a22a9269cc6c85");
+        assert_eq!(def_id.len(), 16);
+        assert_eq!(source.file, hash_file_name(&main_rs));
+        assert_eq!((source.line_start, source.line_end), (92, 96));
+        assert!(source.span.is_none() && source.def_path.is_none() && factorial.ty.is_none());
+
+        // readable names instead of hashes
+        let (crate_id, def_id, source, factorial) = match_source(&["--identifiers=paths"]);
+        assert_eq!(crate_id, "unit_tests");
+        assert_eq!(def_id, "match_test");
+        assert_eq!(source.file, main_rs);
+        let span = source.span.unwrap();
+        assert_eq!((span.file_name.as_str(), span.line_start, span.column_start), ("src/main.rs", 92, 5));
+        assert_eq!(source.def_path.as_deref(), Some("match_test"));
+        assert_eq!(factorial.def_path.as_deref(), Some("Math::factorial"));
+        assert_eq!(factorial.ty.as_deref(), Some("fn(u32) -> u32"));
+
+        // readable names next to the hashes
+        let (crate_id, def_id, source, _) = match_source(&["--identifiers=both"]);
+        assert_eq!(crate_id, "e0a22a9269cc6c85");
+        assert_eq!(def_id.len(), 16);
+        assert_eq!(source.file, hash_file_name(&main_rs));
+        assert_eq!(source.def_path.as_deref(), Some("match_test"));
+        assert!(source.span.is_some());
+    }
+    #[test]
+    fn idioms() {
+        let idioms = |args: &[&str]| {
+            let (_, parsed_jsons) = salt_output(PATH, args);
+            let result: PrintResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+            let visit: VisitorJson = serde_json::from_value(result.visit_res).unwrap();
+            visit.idioms.into_iter()
+                .map(|idiom| (idiom.source.line_start, idiom.source.line_end, idiom.kind, idiom.suggestion))
+                .collect::<Vec<_>>()
+        };
+        let assert_idioms = |found: &[(usize, usize, String, String)], expected: &[(usize, usize, &str, &str)]| {
+            for (line_start, line_end, kind, suggestion) in expected {
+                let idiom = (*line_start, *line_end, kind.to_string(), suggestion.to_string());
+                assert!(found.contains(&idiom), "Idiom {:?} not found in {:?}", idiom, found);
+            }
+        };
+
+        // the learner's names are left out of hashed records
+        assert_idioms(&idioms(&[]), &[
+            (81, 83, "CountLoop", "iter.count()"),
+            (260, 262, "IndexLoop", "for item in &v"),
+            (263, 265, "IndexLoop", "for (i, item) in v.iter_mut().enumerate()"),
+            (267, 271, "PushLoop", "iter.filter(..).collect()"),
+            (273, 277, "CountLoop", "iter.filter(..).count()"),
+            (279, 283, "MinMaxLoop", "iter.max()"),
+            (285, 288, "WhileIndex", "for i in start..end"),
+        ]);
+        assert_idioms(&idioms(&["--identifiers=paths"]), &[
+            (260, 262, "IndexLoop", "for item in &scores"),
+            (267, 271, "PushLoop", "scores.iter().filter(..).collect()"),
+            (285, 288, "WhileIndex", "for i in 0..grid.len()"),
+        ]);
+
+        // hints point at the head of the loop
+        let hints = salt_stderr(PATH, &["--hints"]);
+        assert!(hints.contains("warning: this loop indexes a collection with its counter\n   --> src/main.rs:260:5"), "{}", hints);
+        assert!(hints.contains("help: consider `scores.iter().filter(..).count()`"), "{}", hints);
+        assert!(hints.contains("warning: this loop searches for the largest element by hand"), "{}", hints);
+        assert!(!salt_stderr(PATH, &[]).contains("warning: this loop"));
+
+        // `best` has to be assigned the element it was compared with
+        assert!(!idioms(&[]).iter().any(|(line_start, ..)| *line_start == 345), "A reset isn't a search");
+    }
+    #[test]
+    fn match_patterns() {
+        // test-project fails borrowck, but its matches type-check
+        let (_, parsed_jsons) = salt_output(TEST_PROJECT_PATH, &["--identifiers=paths"]);
+        let result: PrintResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+        let visit: VisitorJson = serde_json::from_value(result.visit_res).unwrap();
+        let in_file = |file: &str| visit.matches.iter().filter_map(|block| match block {
+            BlockJson::Match { source, scrutinee, arm_patterns, all_variants_listed, .. } if source.file.ends_with(file) => {
+                Some((source.line_start, scrutinee.clone(), arm_patterns.clone(), *all_variants_listed))
+            }
+            _ => None,
+        }).collect::<Vec<_>>();
+
+        // only `let` patterns
+        assert!(in_file("/patterns.rs").is_empty());
+
+        let matches = in_file("/bindings_after_at_or_patterns_slice_patterns_box_patterns.rs");
+        assert_eq!(matches.len(), 17);
+        let wildcard = ArmJson { wildcard: true, ..ArmJson::default() };
+        for (line, scrutinee, first_arm, all_variants_listed) in [
+            // `a @ [.., _]`
+            (11, "Slice", ArmJson { at_bindings: 1, slice: true, ..ArmJson::default() }, None),
+            // `foo @ Some(Test::Foo | Test::Bar)`, leaving `None` to the wildcard
+            (70, "Option", ArmJson { at_bindings: 1, or_pattern: true, ..ArmJson::default() }, Some(false)),
+            // `ref foo @ Some(box ref s)`
+            (106, "Option", ArmJson { bindings: 1, at_bindings: 1, box_pattern: true, ..ArmJson::default() }, Some(false)),
+            // `[_, ref a @ Some(box Test::Foo | box Test::Bar), ..]`
+            (181, "Slice", ArmJson { at_bindings: 1, or_pattern: true, slice: true, box_pattern: true, ..ArmJson::default() }, None),
+        ] {
+            let expected = (line, scrutinee.to_string(), vec![first_arm, wildcard.clone()], all_variants_listed);
+            assert!(matches.contains(&expected), "Match {:?} not found in {:?}", expected, matches);
+        }
+    }
+    #[test]
+    fn records() {
+        // the unit tests and the bin target are both checked, each as its own labeled record
+        let (status, parsed_jsons) = salt_output(PATH, &["--", "--all-targets"]);
+        assert!(status.success());
+        let mut targets = parsed_jsons.iter().map(|record| {
+            let result: PrintResult = serde_json::from_value(record.clone()).unwrap();
+            assert_eq!(result.krate.crate_type, "bin");
+            assert!(result.krate.workspace_member);
+            result.krate.target_kind
+        }).collect::<Vec<_>>();
+        targets.sort();
+        assert_eq!(targets, vec!["bin", "test"]);
+    }
+    #[test]
+    fn filters() {
+        let analyzed = |filter: &[&str]| {
+            let args = [&["--identifiers=paths"], filter].concat();
+            let (status, parsed_jsons) = salt_output(FILTER_PATH, &args);
+            assert!(status.success());
+            let mut crates = parsed_jsons.iter().map(|record| {
+                let result: PrintResult = serde_json::from_value(record.clone()).unwrap();
+                format!("{}:{}", result.krate.crate_id, result.krate.target_kind)
+            }).collect::<Vec<_>>();
+            crates.sort();
+            crates
+        };
+
+        let all = vec!["filter_tests:bin", "filter_tests:lib", "helper:lib"];
+        assert_eq!(analyzed(&[]), all);
+        assert_eq!(analyzed(&["--workspace"]), all);
+        // the other packages are still compiled, since filter_tests depends on helper
+        assert_eq!(analyzed(&["--package", "helper"]), vec!["helper:lib"]);
+        assert_eq!(analyzed(&["--package", "filter_tests"]), vec!["filter_tests:bin", "filter_tests:lib"]);
+        assert_eq!(analyzed(&["--file", "src/main.rs"]), vec!["filter_tests:bin"]);
+        assert_eq!(analyzed(&["--file", "helper/src/lib.rs"]), vec!["helper:lib"]);
+
+        let (status, parsed_jsons) = salt_output(FILTER_PATH, &["--package", "helpr"]);
+        assert!(!status.success() && parsed_jsons.is_empty());
+        assert!(salt_stderr(FILTER_PATH, &["--package", "helpr"]).contains("No such package: helpr"));
+    }
+    #[test]
+    fn schema() {
+        let printed = Command::new("cargo")
+            .args(["salt", "--print-schema"])
+            .current_dir(PATH)
+            .output()
+            .expect("Failed to execute cargo salt");
+        assert!(printed.status.success());
+        let schema: serde_json::Value = serde_json::from_slice(&printed.stdout).unwrap();
+        assert_eq!(schema, serde_json::to_value(schemars::schema_for!(PrintResult)).unwrap());
+
+        // every field of a real record is described, and required
+        let (_, parsed_jsons) = salt_output(PATH, &[]);
+        let record = parsed_jsons[0].as_object().unwrap();
+        assert_eq!(record["schema_version"], SCHEMA_VERSION);
+        let keys = |object: &serde_json::Map<String, serde_json::Value>| {
+            let mut keys = object.keys().cloned().collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+        assert_eq!(keys(schema["properties"].as_object().unwrap()), keys(record));
+        assert_eq!(schema["required"].as_array().unwrap().len(), record.len());
+        let visitor = &schema["definitions"]["VisitorJson"];
+        assert_eq!(keys(visitor["properties"].as_object().unwrap()), keys(record["visit_res"].as_object().unwrap()));
+    }
+    #[test]
+    fn capabilities() {
+        let printed = Command::new("cargo")
+            .args(["salt", "--capabilities"])
+            .current_dir(PATH)
+            .output()
+            .expect("Failed to execute cargo salt");
+        assert!(printed.status.success());
+        let capabilities: CapabilitiesJson = serde_json::from_slice(&printed.stdout).unwrap();
+        assert_eq!(capabilities.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(capabilities.toolchain, "nightly-2025-03-03");
+        assert_eq!(capabilities.schema_version, SCHEMA_VERSION);
+        assert_eq!(
+            capabilities.analyses,
+            vec!["style", "borrowck", "borrows", "moves", "drops", "fixes", "mismatches", "query", "liveness"]
+        );
+        assert_eq!(capabilities.formats, vec!["json", "jsonl", "pretty"]);
+        assert_eq!(capabilities.identifiers, vec!["hashed", "paths", "both"]);
+    }
+    #[test]
+    fn server() {
+        let request = |id: u32, method: &str, params: serde_json::Value| {
+            serde_json::json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+        };
+        let responses = salt_server(FILTER_PATH, &["--identifiers=paths"], &[
+            request(1, "crates", serde_json::Value::Null),
+            request(2, "style", serde_json::json!({"file": "src/main.rs"})),
+            request(3, "style", serde_json::json!({"package": "helper"})),
+            // the same crate again, from the same process
+            request(4, "style", serde_json::json!({"package": "helper"})),
+            request(5, "borrowck", serde_json::json!({"file": "src/lib.rs"})),
+            request(6, "borrows", serde_json::json!({"package": "helper", "args": ["--fn", "double"]})),
+            request(7, "unknown", serde_json::Value::Null),
+            request(8, "style", serde_json::json!({"args": ["--identifiers=hashed"]})),
+            serde_json::json!({"jsonrpc": "2.0", "method": "shutdown"}),
+            request(9, "crates", serde_json::Value::Null),
+        ]);
+        // the notification gets no response, and nothing is read after it
+        let ids = responses.iter().map(|response| response["id"].as_u64().unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids, (1..=8).collect::<Vec<_>>());
+
+        let mut crates = serde_json::from_value::<Vec<TargetJson>>(responses[0]["result"].clone()).unwrap()
+            .into_iter()
+            .map(|target| format!("{}:{}", target.package, target.root.file_name().unwrap().to_string_lossy()))
+            .collect::<Vec<_>>();
+        crates.sort();
+        assert_eq!(crates, vec!["filter_tests:lib.rs", "filter_tests:main.rs", "helper:lib.rs"]);
+
+        let records = |response: &serde_json::Value| {
+            response["result"].as_array().unwrap().iter().map(|record| {
+                let result: PrintResult = serde_json::from_value(record.clone()).unwrap();
+                let visit: VisitorJson = serde_json::from_value(result.visit_res).unwrap();
+                let mut fns = visit.fns.keys().cloned().collect::<Vec<_>>();
+                fns.sort();
+                (format!("{}:{}", result.krate.crate_id, result.krate.target_kind), fns)
+            }).collect::<Vec<_>>()
+        };
+        assert_eq!(records(&responses[1]), vec![("filter_tests:bin".to_string(), vec!["main".to_string()])]);
+        assert_eq!(records(&responses[2]), vec![("helper:lib".to_string(), vec!["double".to_string()])]);
+        assert_eq!(records(&responses[3]), records(&responses[2]));
+
+        let borrowck: Vec<BorrowckResult> = serde_json::from_value(responses[4]["result"].clone()).unwrap();
+        assert_eq!(borrowck.len(), 1);
+        assert_eq!(borrowck[0].krate.target_kind, "lib");
+        assert!(borrowck[0].errors.is_empty());
+        let borrows: Vec<BorrowsResult> = serde_json::from_value(responses[5]["result"].clone()).unwrap();
+        assert_eq!(borrows[0].krate.crate_id, "helper");
+
+        assert_eq!(responses[6]["error"]["code"], -32601);
+        assert_eq!(responses[7]["error"]["code"], -32602);
+
+        // a workspace of its own, since the test edits the dependency between requests
+        let dir = std::env::temp_dir().join("salt-server-tests");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("helper/src")).unwrap();
+        std::fs::copy("rust-toolchain.toml", dir.join("rust-toolchain.toml")).unwrap();
+        std::fs::write(dir.join("Cargo.toml"), "[package]\nname = \"server_tests\"\nversion = \"0.1.0\"\nedition = \"2024\"\n\n\
+            [dependencies]\nhelper = { path = \"helper\" }\n\n[workspace]\nmembers = [\"helper\"]\n").unwrap();
+        std::fs::write(dir.join("src/main.rs"), "fn main() {\n    for item in helper::items() {\n        println!(\"{}\", item);\n    }\n}\n").unwrap();
+        std::fs::write(dir.join("helper/Cargo.toml"), "[package]\nname = \"helper\"\nversion = \"0.1.0\"\nedition = \"2024\"\n").unwrap();
+        let helper_rs = dir.join("helper/src/lib.rs");
+        std::fs::write(&helper_rs, "pub fn items() -> Vec<u32> {\n    vec![1, 2]\n}\n").unwrap();
+
+        let mut server = ServerProcess::start(dir.to_str().unwrap(), &[]);
+        let iterates = |response: serde_json::Value| {
+            let records = response["result"].as_array().unwrap().clone();
+            assert_eq!(records.len(), 1, "Expected the bin's record, got {:?}", response);
+            records[0]["visit_res"]["loops"][0]["Loop"]["iterates"].clone()
+        };
+        let main_rs = request(1, "style", serde_json::json!({"file": "src/main.rs"}));
+        assert_eq!(iterates(server.request(&main_rs)), "Value");
+        // only the bin is analyzed, against the helper as it is now
+        std::fs::write(&helper_rs, "pub fn items() -> std::collections::VecDeque<u32> {\n    vec![1, 2].into()\n}\n").unwrap();
+        assert_eq!(iterates(server.request(&main_rs)), "Other");
+
+        // type errors still give the record of what type-checked, a crate that doesn't parse gives an error
+        std::fs::write(dir.join("src/main.rs"), "fn main() {\n    let n: u32 = \"one\";\n}\n").unwrap();
+        let response = server.request(&main_rs);
+        assert_eq!(response["result"][0]["partial"], true, "{:?}", response);
+        std::fs::write(dir.join("src/main.rs"), "fn main() {\n").unwrap();
+        let response = server.request(&main_rs);
+        assert_eq!(response["error"]["code"], -32603, "{:?}", response);
+        assert!(response["error"]["message"].as_str().unwrap().starts_with("Compiling server_tests failed"));
+        // and so does a dependency that doesn't
+        std::fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(&helper_rs, "pub fn items( {}\n").unwrap();
+        let response = server.request(&main_rs);
+        assert!(response["error"]["message"].as_str().unwrap().starts_with("Compiling helper failed"), "{:?}", response);
+
+        // a new target is recorded on the next request, without `reload`
+        std::fs::write(&helper_rs, "pub fn items() -> Vec<u32> {\n    vec![1, 2]\n}\n").unwrap();
+        std::fs::create_dir_all(dir.join("src/bin")).unwrap();
+        std::fs::write(dir.join("src/bin/extra.rs"), "fn main() {}\n").unwrap();
+        let mut crates = server.request(&request(2, "crates", serde_json::Value::Null))["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|target| target["crate_name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        crates.sort();
+        assert_eq!(crates, vec!["extra", "helper", "server_tests"]);
+        let records = server.request(&request(3, "style", serde_json::json!({"file": "src/bin/extra.rs"})));
+        assert_eq!(records["result"].as_array().unwrap().len(), 1, "{:?}", records);
+    }
+    #[test]
+    fn cache() {
+        // a crate of its own, since the test edits it
+        let dir = std::env::temp_dir().join("salt-cache-tests");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::copy("rust-toolchain.toml", dir.join("rust-toolchain.toml")).unwrap();
+        std::fs::write(dir.join("Cargo.toml"), "[package]\nname = \"cache_tests\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n").unwrap();
+        let main_rs = dir.join("src/main.rs");
+        let source = "fn main() {\n    println!(\"{}\", double(2) + triple(2) + total() + sides(Shape::Square));\n}\n\n\
+            fn double(x: u32) -> u32 {\n    x * 2\n}\n\nfn triple(x: u32) -> u32 {\n    x * 3\n}\n\n\
+            fn items() -> Vec<u32> {\n    vec![1, 2]\n}\n\n\
+            fn total() -> u32 {\n    let mut sum = 0;\n    for item in items() {\n        sum += item;\n    }\n    sum\n}\n\n\
+            enum Shape {\n    Circle,\n    Square,\n}\n\n\
+            fn sides(shape: Shape) -> u32 {\n    match shape {\n        Shape::Circle => 0,\n        Shape::Square => 4,\n        _ => 1,\n    }\n}\n";
+        std::fs::write(&main_rs, source).unwrap();
+        let path = dir.to_str().unwrap();
+
+        let result = |parsed_jsons: Vec<serde_json::Value>| {
+            assert_eq!(parsed_jsons.len(), 1);
+            serde_json::from_value::<PrintResult>(parsed_jsons[0].clone()).unwrap()
+        };
+        let (status, parsed_jsons) = salt_output(path, &[]);
+        assert!(status.success());
+        let first = result(parsed_jsons);
+        assert_eq!(first.cache.reused, 0);
+        let owners = first.cache.recomputed;
+        assert!(owners >= 6, "Expected main, double, triple, items, total, Shape and sides, got {:?}", first.cache);
+
+        // the same sources: every owner is reused, with the same results
+        let touched = std::time::SystemTime::now() + std::time::Duration::from_secs(2);
+        std::fs::File::options().write(true).open(&main_rs).unwrap().set_modified(touched).unwrap();
+        let (_, parsed_jsons) = salt_rerun(path, &[]);
+        let second = result(parsed_jsons);
+        assert_eq!((second.cache.reused, second.cache.recomputed), (owners, 0));
+        assert_eq!(second.visit_res, first.visit_res);
+
+        // only the edited function is visited again
+        std::fs::write(&main_rs, source.replace("x * 3", "x + x + x")).unwrap();
+        let (_, parsed_jsons) = salt_rerun(path, &[]);
+        let third = result(parsed_jsons);
+        assert_eq!((third.cache.reused, third.cache.recomputed), (owners - 1, 1));
+
+        // `total` is unchanged, but what it iterates isn't
+        let iterates = |result: &PrintResult| result.visit_res["loops"][0]["Loop"]["iterates"].clone();
+        assert_eq!(iterates(&third), "Value");
+        let edited = source.replace("x * 3", "x + x + x").replace("Vec<u32> {\n    vec![1, 2]", "std::collections::VecDeque<u32> {\n    vec![1, 2].into()");
+        std::fs::write(&main_rs, &edited).unwrap();
+        let (_, parsed_jsons) = salt_rerun(path, &[]);
+        let fourth = result(parsed_jsons);
+        assert_eq!((fourth.cache.reused, fourth.cache.recomputed), (owners - 2, 2));
+        assert_eq!(iterates(&fourth), "Other");
+
+        // a new variant changes every match on the enum, so nothing is reused
+        let listed = |result: &PrintResult| result.visit_res["matches"][0]["Match"]["all_variants_listed"].clone();
+        assert_eq!(listed(&fourth), true);
+        std::fs::write(&main_rs, edited.replace("Square,\n}", "Square,\n    Triangle,\n}")).unwrap();
+        let (_, parsed_jsons) = salt_rerun(path, &[]);
+        let fifth = result(parsed_jsons);
+        assert_eq!((fifth.cache.reused, fifth.cache.recomputed), (0, owners));
+        assert_eq!(listed(&fifth), false);
+
+        // without the cache, everything is
+        std::fs::write(&main_rs, source).unwrap();
+        let (_, parsed_jsons) = salt_rerun(path, &["--no-cache"]);
+        let sixth = result(parsed_jsons);
+        assert_eq!((sixth.cache.reused, sixth.cache.recomputed), (0, owners));
+        assert_eq!(sixth.visit_res, first.visit_res);
+    }
+}
@@ -0,0 +1,56 @@
+#![allow(warnings)]
+
+fn main() {}
+
+fn consume(s: String) {}
+
+// E0382 after passing to a function
+fn moved_into_call() {
+    let s = String::from("hi");
+    consume(s);
+    println!("{}", s);
+}
+
+// E0382 after a for loop
+fn moved_into_loop() {
+    let v = vec![1, 2, 3];
+    for x in v {}
+    println!("{}", v.len());
+}
+
+// E0382 after a move closure
+fn moved_into_closure() {
+    let s = String::from("hi");
+    let f = move || s.len();
+    println!("{}", s);
+}
+
+// moved in only one branch
+fn moved_on_one_path(cond: bool) {
+    let s = String::from("hi");
+    let t;
+    if cond {
+        t = s;
+    }
+    println!("{}", s);
+}
+
+// reinitialized before the next use
+fn reassigned() {
+    let mut s = String::from("hi");
+    let t = s;
+    s = String::from("again");
+    println!("{} {}", s, t);
+}
+
+struct Pair {
+    a: String,
+    b: String,
+}
+
+// partial move of a field
+fn partial_move(p: Pair) {
+    let a = p.a;
+    println!("{}", p.b);
+    let q = p;
+}
@@ -0,0 +1,22 @@
+#![allow(warnings)]
+
+fn main() {}
+
+struct Pair {
+    a: String,
+    b: String,
+}
+
+fn consume(s: String) {}
+
+fn states(p: Pair) {
+    let mut v = vec![1, 2, 3];
+    let first = &v[0];
+    let s = String::from("hi");
+    let later;
+    consume(s);
+    println!("{}", first);
+    consume(p.a);
+    later = 5;
+    v.push(later);
+}
@@ -1,44 +1,106 @@
-use std::process::Command;
-use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
 use std::path::Path;
-use rustc_session::config::parse;
 use serde_json::Value;
 use crate::plugin::visit_hir::*;
 use crate::plugin::print_result::*;
 use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
 
+// tests sharing a test crate would otherwise clean it out from under each other
+static SALT_LOCK: Mutex<()> = Mutex::new(());
+
+// the style report with def paths for ids, so functions can be found by name
 pub(crate) fn run_salt(path: &str) -> VisitorJson {
-    let project_dir = Path::new(path);
-    env::set_current_dir(project_dir).expect("Failed to change directory");
-    
-    let output = Command::new("cargo")
-        .args(["salt"])
-        .output()
-        .expect("Failed to execute cargo salt");
+    let (status, parsed_jsons) = salt_output(path, &["--identifiers=paths"]);
+    assert!(status.success(), "Command failed: {:?}", status);
+    assert!(parsed_jsons.len() == 1, "Expected 1 JSON object, got {}: {:?}", parsed_jsons.len(), parsed_jsons);
 
-    assert!(output.status.success(), "Command failed: {:?}", output);
+    let print_result: PrintResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
+    let mut visit: VisitorJson = serde_json::from_value(print_result.visit_res).unwrap();
+    // spans, def paths and signatures are checked in `identifiers`, blocks are compared by file and lines
+    let blocks = visit.fns.values_mut()
+        .chain(&mut visit.loops)
+        .chain(&mut visit.matches)
+        .chain(&mut visit.let_exprs)
+        .chain(&mut visit.iter_mthds)
+        .chain(&mut visit.unsafe_blocks)
+        .chain(&mut visit.no_type)
+        .chain(&mut visit.closures);
+    for block in blocks {
+        let (BlockJson::Loop { source, .. } | BlockJson::Match { source, .. } | BlockJson::LetExpr { source, .. }
+            | BlockJson::Unsafe { source, .. } | BlockJson::Iter { source, .. } | BlockJson::Def { source, .. }
+            | BlockJson::NoType { source, .. } | BlockJson::Closure { source, .. }) = block;
+        source.span = None;
+        source.def_path = None;
+        source.ty = None;
+    }
+    visit
+}
 
-    let output_str = String::from_utf8_lossy(&output.stdout);
+// runs `cargo salt <args>` in the test crate at `path` and reads every record it writes
+pub(crate) fn salt_output(path: &str, args: &[&str]) -> (ExitStatus, Vec<Value>) {
+    let _guard = SALT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
 
-    let json_objects: Vec<&str> = output_str
-    .split('\n')
-    .filter(|line| !line.trim().is_empty())
-    .collect();
+    // cargo skips the driver entirely for crates it considers fresh
+    Command::new("cargo")
+        .arg("clean")
+        .current_dir(Path::new(path))
+        .output()
+        .expect("Failed to execute cargo clean");
+    run_cargo_salt(path, args)
+}
 
-    let parsed_jsons: Vec<Value> = json_objects
-        .into_iter()
-        .map(|json_str| {
-            serde_json::from_str(json_str)
-                .expect("Failed to parse JSON output")
-        })
-        .collect();
+// like `salt_output`, but keeping the target directory and the cache in it. cargo only runs
+// the driver again for crates whose sources changed since the last run.
+pub(crate) fn salt_rerun(path: &str, args: &[&str]) -> (ExitStatus, Vec<Value>) {
+    let _guard = SALT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    run_cargo_salt(path, args)
+}
 
-    assert!(parsed_jsons.len() == 1, "Expected 1 JSON object, got {}: {:?}", parsed_jsons.len(), parsed_jsons);
+// what `cargo salt <args>` prints to stderr for the test crate at `path`, eg. its warnings
+pub(crate) fn salt_stderr(path: &str, args: &[&str]) -> String {
+    let _guard = SALT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    Command::new("cargo")
+        .arg("clean")
+        .current_dir(Path::new(path))
+        .output()
+        .expect("Failed to execute cargo clean");
+    let output = Command::new("cargo")
+        .arg("salt")
+        .args(args)
+        .current_dir(Path::new(path))
+        .output()
+        .expect("Failed to execute cargo salt");
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
 
-    let print_result: PrintResult = serde_json::from_value(parsed_jsons[0].clone()).unwrap();
-    let visit: VisitorJson = serde_json::from_value(print_result.visit_res).unwrap();
-    visit
+fn run_cargo_salt(path: &str, args: &[&str]) -> (ExitStatus, Vec<Value>) {
+    let records = std::env::temp_dir().join(format!("salt-{}.json", Path::new(path).file_name().unwrap().to_string_lossy()));
+    let output = Command::new("cargo")
+        .arg("salt")
+        .arg("--output")
+        .arg(&records)
+        .args(["--format", "json"])
+        .args(args)
+        .current_dir(Path::new(path))
+        .output()
+        .expect("Failed to execute cargo salt");
+
+    let contents = std::fs::read_to_string(&records).expect("Failed to read the output file");
+    let parsed_jsons: Vec<Value> = if contents.trim().is_empty() {
+        Vec::new()
+    } else {
+        serde_json::from_str(&contents).expect("Failed to parse JSON output")
+    };
 
+    (output.status, parsed_jsons)
+}
+
+// where a block of the test crate at `path` should be, named by its real path
+pub(crate) fn source(path: &str, file: &str, line_start: usize, line_end: usize) -> SourceJson {
+    let file = std::fs::canonicalize(Path::new(path).join(file)).unwrap();
+    SourceJson { file: file.display().to_string(), line_start, line_end, span: None, def_path: None, ty: None }
 }
 
 pub(crate) fn compare_fn(name: &str, expected: &BlockJson, functions: &HashMap<String, BlockJson>) ->  String{
@@ -55,8 +117,8 @@ pub(crate) fn compare_fn(name: &str, expected: &BlockJson, functions: &HashMap<S
     assert!(
         expected == actual || 
         // very unfortunate json teardown to determine set equivalencies in param tykinds
-        if let (BlockJson::Def{ params: p_a, ret: ret_a, unsafety: u_a, recursive: rec_a, lines: l_a },
-                BlockJson::Def{ params: p_e, ret: ret_e, unsafety: u_e, recursive: rec_e, lines: l_e }) = (actual, expected) {
+        if let (BlockJson::Def{ source: s_a, params: p_a, ret: ret_a, unsafety: u_a, recursive: rec_a, lines: l_a },
+                BlockJson::Def{ source: s_e, params: p_e, ret: ret_e, unsafety: u_e, recursive: rec_e, lines: l_e }) = (actual, expected) {
             let eq_tykinds = match (p_a.get("ty_kinds").unwrap(), p_e.get("ty_kinds").unwrap()) {
                 (Value::Array(tys_a), Value::Array(tys_e)) => {
                     let set1: HashSet<String> = tys_a.iter()
@@ -71,7 +133,7 @@ pub(crate) fn compare_fn(name: &str, expected: &BlockJson, functions: &HashMap<S
             };
             eq_tykinds
             && p_a.get("closure_traits") == p_e.get("closure_traits")
-            && u_a == u_e && ret_a == ret_e && rec_a == rec_e && l_a == l_e
+            && u_a == u_e && ret_a == ret_e && rec_a == rec_e && l_a == l_e && s_a == s_e
         } else {
             false
         }
@@ -83,4 +145,69 @@ pub(crate) fn compare_fn(name: &str, expected: &BlockJson, functions: &HashMap<S
     );
 
     def_id.to_string()
-}
\ No newline at end of file
+}
+
+// sends each request to a `salt-server <args>` started in the test crate at `path`,
+// and reads the responses it writes before stdin closes
+pub(crate) fn salt_server(path: &str, args: &[&str], requests: &[Value]) -> Vec<Value> {
+    let _guard = SALT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut server = Command::new("salt-server")
+        .args(args)
+        .current_dir(Path::new(path))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start salt-server");
+    let mut stdin = server.stdin.take().unwrap();
+    for request in requests {
+        writeln!(stdin, "{}", request).unwrap();
+    }
+    drop(stdin);
+
+    let output = server.wait_with_output().expect("Failed to wait for salt-server");
+    assert!(output.status.success(), "salt-server failed: {:?}", output.status);
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Failed to parse a response"))
+        .collect()
+}
+
+// a `salt-server <args>` started in the test crate at `path`, for tests that change the crate
+// between requests
+pub(crate) struct ServerProcess {
+    server: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ServerProcess {
+    pub(crate) fn start(path: &str, args: &[&str]) -> Self {
+        let mut server = Command::new("salt-server")
+            .args(args)
+            .current_dir(Path::new(path))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start salt-server");
+        let stdin = server.stdin.take().unwrap();
+        let stdout = BufReader::new(server.stdout.take().unwrap());
+        ServerProcess { server, stdin, stdout }
+    }
+
+    // sends the request and waits for its response
+    pub(crate) fn request(&mut self, request: &Value) -> Value {
+        writeln!(self.stdin, "{}", request).unwrap();
+        let mut line = String::new();
+        self.stdout.read_line(&mut line).expect("Failed to read a response");
+        serde_json::from_str(&line).expect("Failed to parse a response")
+    }
+}
+
+impl Drop for ServerProcess {
+    fn drop(&mut self) {
+        let _ = self.server.kill();
+        let _ = self.server.wait();
+    }
+}
@@ -1,6 +1,6 @@
 #![allow(warnings)]
+#![feature(let_chains)]
 use std::cell::UnsafeCell;
-
 mod other;
 
 fn main() {
@@ -176,4 +176,191 @@ fn nested_ifs(one: bool, two: bool) {
 
 mod another {
     fn mod_test() {}
-}
\ No newline at end of file
+}
+
+//closure capture tests
+fn capture_styles() {
+    let mut count = 0;
+    let name = String::from("salt");
+    let mut inc = || count += 1;
+    inc();
+    let greet = || println!("{}", name);
+    greet();
+    let owned = move || name.len();
+    owned();
+}
+
+fn make_adder(n: u32) -> impl Fn(u32) -> u32 {
+    move |x| x + n
+}
+
+fn spawn_worker(v: Vec<u32>) {
+    let handle = std::thread::spawn(move || v.len());
+    handle.join().unwrap();
+}
+
+//iterator pipeline tests
+struct Countdown(u32);
+
+impl Iterator for Countdown {
+    type Item = u32;
+    fn next(&mut self) -> Option<u32> {
+        self.0 = self.0.checked_sub(1)?;
+        Some(self.0)
+    }
+}
+
+fn pipelines(words: Vec<String>) -> Vec<usize> {
+    let evens: u32 = (0..10).filter(|n| n % 2 == 0).sum();
+    for n in Countdown(evens) {
+        println!("{}", n);
+    }
+    for word in &words {
+        println!("{}", word);
+    }
+    words.iter().map(|w| w.len()).collect()
+}
+
+//loop kind tests
+fn loop_kinds(mut v: Vec<u32>, mut stack: Vec<u32>) -> u32 {
+    'outer: for x in &v {
+        for y in 0..*x {
+            if y == 2 {
+                continue 'outer;
+            }
+            if y == 5 {
+                break 'outer;
+            }
+        }
+    }
+    for x in &mut v {
+        *x += 1;
+    }
+    while let Some(top) = stack.pop() {
+        if top == 0 {
+            continue;
+        }
+    }
+    let mut n = 0;
+    let found = loop {
+        n += 1;
+        if n == 3 {
+            break n * 2;
+        }
+    };
+    for x in v {
+        println!("{}", x);
+    }
+    found
+}
+
+//imperative idiom tests
+fn idioms(scores: Vec<u32>, mut grid: Vec<u32>) -> u32 {
+    let mut total = 0;
+    for i in 0..scores.len() {
+        total += scores[i];
+    }
+    for i in 0..grid.len() {
+        grid[i] = grid[i] * i as u32;
+    }
+    let mut passed = Vec::new();
+    for score in &scores {
+        if *score > 50 {
+            passed.push(*score);
+        }
+    }
+    let mut failures = 0;
+    for score in scores.iter() {
+        if *score < 50 {
+            failures += 1;
+        }
+    }
+    let mut best = 0;
+    for score in &scores {
+        if *score > best {
+            best = *score;
+        }
+    }
+    let mut i = 0;
+    while i < grid.len() {
+        total += grid[i];
+        i += 1;
+    }
+    total + failures + best
+}
+
+//match analysis tests
+enum Shape {
+    Circle(u32),
+    Square { side: u32 },
+    Empty,
+}
+
+fn match_features(shape: &Shape, grade: u8, pair: (bool, bool), name: &str) -> u32 {
+    let area = match shape {
+        Shape::Circle(r) if *r > 10 => 3 * r * r,
+        Shape::Circle(r) => 3 * r * r,
+        Shape::Square { side } => side * side,
+        Shape::Empty => 0,
+    };
+    let bonus = match grade {
+        90..=100 => 2,
+        70..=89 | 60 => 1,
+        _ => 0,
+    };
+    let both = match pair {
+        (true, true) => 1,
+        (a, _) => a as u32,
+    };
+    match name {
+        "salt" => area + bonus + both,
+        other => other.len() as u32,
+    }
+}
+
+//let expression tests
+fn let_forms(value: Option<u32>, parsed: Result<u32, String>, shape: &Shape) -> u32 {
+    let Some(base) = value else {
+        return 0;
+    };
+    let mut total = base;
+    if let Ok(n) = parsed {
+        total += n;
+    } else {
+        total += 1;
+    }
+    if let Shape::Circle(r) = shape && let Some(v) = value {
+        total += r + v;
+    }
+    let mut stack = vec![1, 2];
+    while let Some(top) = stack.pop() && top > 1 {
+        total += top;
+    }
+    total
+}
+
+//not idioms
+fn reset_best(scores: Vec<u32>) -> u32 {
+    let mut best = 0;
+    for score in &scores {
+        if *score > best {
+            best = 0;
+        }
+    }
+    best
+}
+
+//closure escape tests
+fn spawn<F: FnOnce() -> usize>(f: F) -> usize {
+    f()
+}
+
+fn run_later<F: FnOnce() -> usize + 'static>(f: F) {}
+
+fn queue(job: Box<dyn FnOnce() -> usize>) {}
+
+fn escapes(v: Vec<u32>, w: Vec<u32>, name: String) -> usize {
+    run_later(move || v.len());
+    queue(Box::new(move || w.len()));
+    spawn(|| name.len())
+}
@@ -0,0 +1,247 @@
+use rustc_hir::def_id::LocalDefId;
+use rustc_hir::intravisit::{self, Visitor};
+use rustc_hir::{Expr, ExprKind, LangItem, LetStmt};
+use rustc_hir_analysis::autoderef::Autoderef;
+use rustc_infer::infer::{InferCtxt, TyCtxtInferExt};
+use rustc_middle::ty::{self, ParamEnv, Ty, TyCtxt, TypeVisitableExt, TypeckResults, TypingMode};
+use rustc_span::sym;
+use rustc_trait_selection::infer::InferCtxtExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::plugin::borrowck::SpanJson;
+use crate::plugin::anonymize::hash_id;
+
+// an expression whose type can't be coerced to the type its context expects (E0308)
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct MismatchJson {
+    pub(crate) def_id: String,
+    pub(crate) span: SpanJson,
+    pub(crate) context: String,
+    pub(crate) expected: String,
+    pub(crate) found: String,
+    pub(crate) autoderef_steps: Vec<String>,
+    pub(crate) deref_count: Option<usize>,
+    pub(crate) coercions: Vec<String>,
+}
+
+// every mismatch in bodies that failed to type-check, keyed by `file:line:column` of the expression
+pub(crate) fn type_mismatches(tcx: TyCtxt) -> BTreeMap<String, MismatchJson> {
+    let mut mismatches = BTreeMap::new();
+    for def_id in tcx.hir_body_owners() {
+        let typeck_results = tcx.typeck(def_id);
+        if typeck_results.tainted_by_errors.is_none() {
+            continue;
+        }
+        let mut finder = MismatchFinder {
+            tcx,
+            def_id,
+            typeck_results,
+            infcx: tcx.infer_ctxt().build(TypingMode::non_body_analysis()),
+            param_env: tcx.param_env(def_id),
+            mismatches: Vec::new(),
+        };
+        let body = tcx.hir_body_owned_by(def_id);
+        finder.visit_body(body);
+        if let Some(ret) = return_ty(tcx, def_id) {
+            finder.check(body_tail(body.value), ret, "Return");
+        }
+
+        for mismatch in finder.mismatches {
+            let key = format!(
+                "{}:{}:{}",
+                mismatch.span.file_name, mismatch.span.line_start, mismatch.span.column_start
+            );
+            mismatches.entry(key).or_insert(mismatch);
+        }
+    }
+    mismatches
+}
+
+// walks the sites where rustc coerces an expression to an expected type
+struct MismatchFinder<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    def_id: LocalDefId,
+    typeck_results: &'a TypeckResults<'tcx>,
+    infcx: InferCtxt<'tcx>,
+    param_env: ParamEnv<'tcx>,
+    mismatches: Vec<MismatchJson>,
+}
+
+impl<'tcx> Visitor<'tcx> for MismatchFinder<'_, 'tcx> {
+    fn visit_local(&mut self, local: &'tcx LetStmt<'tcx>) {
+        if let (Some(_), Some(init)) = (local.ty, local.init) {
+            if let Some(expected) = self.typeck_results.node_type_opt(local.pat.hir_id) {
+                self.check(init, expected, "Let");
+            }
+        }
+        intravisit::walk_local(self, local);
+    }
+
+    fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+        match expr.kind {
+            ExprKind::Call(func, args) => {
+                let func_ty = self.typeck_results.node_type_opt(func.hir_id);
+                if let Some(inputs) = func_ty.and_then(|ty| self.fn_inputs(ty)) {
+                    for (arg, expected) in args.iter().zip(inputs) {
+                        self.check(arg, expected, "Argument");
+                    }
+                }
+            }
+            ExprKind::MethodCall(_, _, args, _) => {
+                if let Some(method) = self.typeck_results.type_dependent_def_id(expr.hir_id) {
+                    let method_ty = Ty::new_fn_def(self.tcx, method, self.typeck_results.node_args(expr.hir_id));
+                    if let Some(inputs) = self.fn_inputs(method_ty) {
+                        // the first input is the receiver
+                        for (arg, expected) in args.iter().zip(inputs.into_iter().skip(1)) {
+                            self.check(arg, expected, "Argument");
+                        }
+                    }
+                }
+            }
+            ExprKind::Ret(Some(value)) => {
+                if let Some(ret) = return_ty(self.tcx, self.def_id) {
+                    self.check(value, ret, "Return");
+                }
+            }
+            ExprKind::Assign(lhs, rhs, _) => {
+                if let Some(expected) = self.typeck_results.expr_ty_opt(lhs) {
+                    self.check(rhs, expected, "Assignment");
+                }
+            }
+            ExprKind::Struct(_, fields, _) => {
+                if let Some(ty::Adt(adt, args)) = self.typeck_results.expr_ty_opt(expr).map(|ty| ty.kind()) {
+                    if adt.is_struct() {
+                        for field in fields.iter() {
+                            let Some(idx) = self.typeck_results.opt_field_index(field.hir_id) else { continue };
+                            let expected = adt.non_enum_variant().fields[idx].ty(self.tcx, args);
+                            self.check(field.expr, expected, "Field");
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        intravisit::walk_expr(self, expr);
+    }
+}
+
+impl<'tcx> MismatchFinder<'_, 'tcx> {
+    fn check(&mut self, expr: &Expr<'tcx>, expected: Ty<'tcx>, context: &str) {
+        let Some(found) = self.typeck_results.expr_ty_opt(expr) else { return };
+        let (found, expected) = (self.tcx.erase_regions(found), self.tcx.erase_regions(expected));
+        if [found, expected].iter().any(|ty| ty.references_error() || ty.has_infer() || ty.has_param())
+            || self.coerces(found, expected)
+        {
+            return;
+        }
+
+        let steps = self.autoderef(found);
+        let deref_count = steps.iter().position(|step| self.same(*step, expected)).filter(|count| *count > 0);
+        self.mismatches.push(MismatchJson {
+            def_id: hash_id(self.tcx, &self.def_id.to_def_id()),
+            span: SpanJson::new(self.tcx.sess.source_map(), expr.span),
+            context: context.to_string(),
+            expected: expected.to_string(),
+            found: found.to_string(),
+            autoderef_steps: steps.iter().map(|step| step.to_string()).collect(),
+            deref_count,
+            coercions: self.coercions(found, expected),
+        });
+    }
+
+    // the edits at the mismatch that would make the expression's type fit
+    fn coercions(&self, found: Ty<'tcx>, expected: Ty<'tcx>) -> Vec<String> {
+        let tcx = self.tcx;
+        let mut coercions = Vec::new();
+        if self.coerces(Ty::new_imm_ref(tcx, tcx.lifetimes.re_erased, found), expected) {
+            coercions.push("&".to_string());
+        }
+        if self.autoderef(found).get(1).is_some_and(|target| self.coerces(*target, expected)) {
+            coercions.push("*".to_string());
+        }
+
+        // `as_ref` turns `&T`-like values into `&U` for `T: AsRef<U>`, and `Option<T>` into `Option<&T>`
+        let as_ref = match (found.kind(), expected.kind()) {
+            (_, ty::Ref(_, target, ty::Mutability::Not)) => {
+                let source = found.builtin_deref(true).unwrap_or(found);
+                self.implements(sym::AsRef, source, Some(*target))
+            }
+            (ty::Adt(found_adt, found_args), ty::Adt(expected_adt, expected_args))
+                if found_adt == expected_adt && tcx.is_diagnostic_item(sym::Option, found_adt.did()) =>
+            {
+                self.same(expected_args.type_at(0), Ty::new_imm_ref(tcx, tcx.lifetimes.re_erased, found_args.type_at(0)))
+            }
+            _ => false,
+        };
+        if as_ref {
+            coercions.push(".as_ref()".to_string());
+        }
+        if self.implements(sym::Into, found, Some(expected)) {
+            coercions.push(".into()".to_string());
+        }
+        if expected.ty_adt_def().is_some_and(|adt| tcx.is_lang_item(adt.did(), LangItem::String))
+            && self.implements(sym::ToString, found, None)
+        {
+            coercions.push(".to_string()".to_string());
+        }
+        coercions
+    }
+
+    // whether rustc's own coercion check accepts `found` where `expected` is expected
+    fn coerces(&self, found: Ty<'tcx>, expected: Ty<'tcx>) -> bool {
+        self.same(found, expected) || rustc_hir_typeck::can_coerce(self.tcx, self.param_env, self.def_id, found, expected)
+    }
+
+    // types from the fn signature can still have regions where typeck's have erased ones
+    fn same(&self, a: Ty<'tcx>, b: Ty<'tcx>) -> bool {
+        self.tcx.erase_regions(a) == self.tcx.erase_regions(b)
+    }
+
+    // the types rustc's autoderef reaches from `ty`, starting with `ty` itself, built in for
+    // references and boxes, otherwise through `Deref::Target`, up to the recursion limit
+    fn autoderef(&self, ty: Ty<'tcx>) -> Vec<Ty<'tcx>> {
+        Autoderef::new(&self.infcx, self.param_env, self.def_id, rustc_span::DUMMY_SP, ty)
+            .silence_errors()
+            .map(|(step, _)| self.tcx.erase_regions(self.infcx.resolve_vars_if_possible(step)))
+            .collect()
+    }
+
+    fn implements(&self, trait_name: rustc_span::Symbol, ty: Ty<'tcx>, arg: Option<Ty<'tcx>>) -> bool {
+        match self.tcx.get_diagnostic_item(trait_name) {
+            Some(trait_def_id) => self.implements_trait(trait_def_id, ty, arg),
+            None => false,
+        }
+    }
+
+    fn implements_trait(&self, trait_def_id: rustc_hir::def_id::DefId, ty: Ty<'tcx>, arg: Option<Ty<'tcx>>) -> bool {
+        let params = std::iter::once(ty).chain(arg).collect::<Vec<_>>();
+        self.infcx.type_implements_trait(trait_def_id, params, self.param_env).must_apply_modulo_regions()
+    }
+
+    // the expected input types of a function or function pointer type
+    fn fn_inputs(&self, ty: Ty<'tcx>) -> Option<Vec<Ty<'tcx>>> {
+        let sig = match ty.kind() {
+            ty::FnDef(..) | ty::FnPtr(..) => ty.fn_sig(self.tcx),
+            _ => return None,
+        };
+        Some(self.tcx.instantiate_bound_regions_with_erased(sig).inputs().to_vec())
+    }
+}
+
+// the declared return type of a function body
+fn return_ty(tcx: TyCtxt<'_>, def_id: LocalDefId) -> Option<Ty<'_>> {
+    if !matches!(tcx.def_kind(def_id), rustc_hir::def::DefKind::Fn | rustc_hir::def::DefKind::AssocFn) {
+        return None;
+    }
+    let sig = tcx.fn_sig(def_id).instantiate_identity();
+    Some(tcx.instantiate_bound_regions_with_erased(sig).output())
+}
+
+// the expression a body evaluates to, looking through the body's outer block
+fn body_tail<'tcx>(value: &'tcx Expr<'tcx>) -> &'tcx Expr<'tcx> {
+    match value.kind {
+        ExprKind::Block(block, _) => block.expr.unwrap_or(value),
+        _ => value,
+    }
+}
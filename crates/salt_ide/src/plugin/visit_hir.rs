@@ -1,32 +1,50 @@
-use rustc_middle::ty::{Ty, TyCtxt, TyKind, ExistentialPredicate};
+use rustc_middle::ty::{AdtDef, Ty, TyCtxt, TyKind, ExistentialPredicate, TypeckResults, UpvarCapture, BorrowKind};
 use rustc_span::source_map::SourceMap;
 use rustc_span::def_id::DefId;
+use rustc_span::{sym, FileName, Span};
 use rustc_hir::intravisit::{self, Visitor};
-use rustc_hir::{Item, BodyId, Expr, ExprKind, ItemKind, PatKind, def::DefKind, OwnerId, MatchSource};
+use rustc_hir::{Item, BodyId, Expr, ExprKind, ItemKind, Arm, Pat, PatKind, PatExpr, PatExprKind, def::{CtorOf, DefKind, Res}, OwnerId, OwnerNode, MatchSource, Node, CaptureBy, ClosureKind, LangItem, QPath, HirId, LetStmt, LoopSource, Destination, BinOpKind, Mutability};
 use rustc_utils::TyExt;
 use rustc_middle::hir::nested_filter;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, hash_map::DefaultHasher};
-use std::hash::{Hash, Hasher};
+use std::collections::{HashMap, HashSet};
 
-// togglable for testing
-const HASH_EN: bool = true;
+use crate::plugin::anonymize::{hash_file_name, hash_id, identifiers};
+use crate::plugin::borrowck::SpanJson;
+use crate::plugin::idioms::{self, IdiomJson};
+use crate::plugin::owner_cache::OwnerCache;
 
 // function parameters (inputs)
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
 pub(crate) struct Params {
     pub(crate) closure_traits: Vec<String>,
     pub(crate) ty_kinds: Vec<(bool, String)>,
 }
 
 // function return arguments (outputs)
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub(crate) struct Return {
     pub(crate) mutabl: bool,
     pub(crate) closure_trait: Option<String>,
     pub(crate) ty_kind: String,
 }
 
+// the pattern features of one match arm
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug, Clone, Default)]
+pub struct ArmJson {
+    // the whole pattern is `_`
+    pub(crate) wildcard: bool,
+    pub(crate) bindings: u32,
+    // `name @ pattern`
+    pub(crate) at_bindings: u32,
+    pub(crate) or_pattern: bool,
+    pub(crate) slice: bool,
+    pub(crate) box_pattern: bool,
+    pub(crate) range: bool,
+    pub(crate) guard: bool,
+}
+
 impl Default for Return {
     fn default() -> Self {
         Return {
@@ -41,31 +59,58 @@ impl Default for Return {
 // raw version of BlockJson used by HirVisitor
 enum Block {
     Loop {
+        span: Span,
         def_id: DefId,
+        // what `break` and `continue` resolve to
+        hir_id: HirId,
         lines: usize,
         depth: usize,
+        kind: &'static str,
+        labeled: bool,
+        breaks: u32,
+        continues: u32,
+        break_with_value: bool,
+        iterates: Option<&'static str>,
     },
     Match {
+        span: Span,
         def_id: DefId,
         lines: usize,
         arms: u32,
         depth: usize,
+        scrutinee: &'static str,
+        arm_patterns: Vec<ArmJson>,
+        all_variants_listed: Option<bool>,
     },
     LetExpr{
+        span: Span,
         def_id: DefId,
         depth: usize,
+        kind: &'static str,
+        chained: bool,
+        scrutinee: &'static str,
+        has_else: bool,
     },
     Unsafe {
+        span: Span,
         def_id: DefId,
         lines: usize,
         depth: usize,
     },
     Iter {
+        span: Span,
         def_id: DefId,
         depth: usize,
         methods: Vec<String>,
+        origin: &'static str,
+        origin_method: Option<String>,
+        adapters: Vec<String>,
+        terminal: Option<String>,
+        collect_type: Option<String>,
     },
     Def {
+        span: Span,
+        def_id: DefId,
         params: Params,
         ret: Return,
         unsafety: bool,
@@ -73,93 +118,192 @@ enum Block {
         lines: usize,
     },
     NoType {
+        span: Span,
+        def_id: DefId,
+        lines: usize,
+        depth: usize,
+        type_error: bool,
+    },
+    Closure {
+        span: Span,
         def_id: DefId,
         lines: usize,
         depth: usize,
+        is_move: bool,
+        captures: Vec<(String, String)>,
+        escapes: Option<String>,
     }
 }
 
 // json version of Block used for serialization
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug, Clone)]
 pub enum BlockJson {
     Loop {
+        source: SourceJson,
         def_id: String,
         lines: usize,
         depth: usize,
+        kind: String,
+        labeled: bool,
+        breaks: u32,
+        continues: u32,
+        break_with_value: bool,
+        iterates: Option<String>,
     },
     Match {
+        source: SourceJson,
         def_id: String,
         lines: usize,
         arms: u32,
         depth: usize,
+        scrutinee: String,
+        arm_patterns: Vec<ArmJson>,
+        all_variants_listed: Option<bool>,
     },
     LetExpr {
+        source: SourceJson,
         def_id: String,
         depth: usize,
+        kind: String,
+        chained: bool,
+        scrutinee: String,
+        has_else: bool,
     },
     Unsafe {
+        source: SourceJson,
         def_id: String,
         lines: usize,
         depth: usize,
     },
     Iter {
+        source: SourceJson,
         def_id: String,
         depth: usize,
         methods: Vec<String>,
+        origin: String,
+        origin_method: Option<String>,
+        adapters: Vec<String>,
+        terminal: Option<String>,
+        collect_type: Option<String>,
     },
     Def {
+        source: SourceJson,
+        #[schemars(with = "Params")]
         params: serde_json::Value,
+        #[schemars(with = "Return")]
         ret: serde_json::Value,
         unsafety: bool,
         recursive: bool,
         lines: usize,
     },
     NoType {
+        source: SourceJson,
+        def_id: String,
+        lines: usize,
+        depth: usize,
+        type_error: bool,
+    },
+    Closure {
+        source: SourceJson,
         def_id: String,
         lines: usize,
         depth: usize,
+        is_move: bool,
+        captures: Vec<(String, String)>,
+        escapes: Option<String>,
     }
 }
 
+// where a block is in the crate's sources, so style can be joined with the extension's per-file logs
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug, Clone)]
+pub struct SourceJson {
+    pub(crate) file: String,
+    pub(crate) line_start: usize,
+    pub(crate) line_end: usize,
+    // readable identifiers: the exact span, the path of the item the block belongs to,
+    // and the signature of a function
+    pub(crate) span: Option<SpanJson>,
+    pub(crate) def_path: Option<String>,
+    pub(crate) ty: Option<String>,
+}
+
 impl Block {
-    fn to_json(&self) -> BlockJson {
+    fn to_json(&self, tcx: TyCtxt) -> BlockJson {
         match self {
-            Block::Loop { def_id, lines, depth} => BlockJson::Loop {
-                def_id: hash_id(def_id),
+            Block::Loop { def_id, span, lines, depth, kind, labeled, breaks, continues, break_with_value, iterates, .. } => BlockJson::Loop {
+                source: source_json(tcx, def_id, *span),
+                def_id: hash_id(tcx, def_id),
                 lines: *lines,
                 depth: *depth,
+                kind: kind.to_string(),
+                labeled: *labeled,
+                breaks: *breaks,
+                continues: *continues,
+                break_with_value: *break_with_value,
+                iterates: iterates.map(str::to_string),
             },
-            Block::Match { def_id, lines, arms, depth } => BlockJson::Match {
-                def_id: hash_id(def_id),
+            Block::Match { def_id, span, lines, arms, depth, scrutinee, arm_patterns, all_variants_listed } => BlockJson::Match {
+                source: source_json(tcx, def_id, *span),
+                def_id: hash_id(tcx, def_id),
                 lines: *lines,
                 arms: *arms,
                 depth: *depth,
+                scrutinee: scrutinee.to_string(),
+                arm_patterns: arm_patterns.clone(),
+                all_variants_listed: *all_variants_listed,
             },
-            Block::LetExpr {def_id, depth } => BlockJson::LetExpr {
-                def_id: hash_id(def_id),
+            Block::LetExpr {def_id, span, depth, kind, chained, scrutinee, has_else } => BlockJson::LetExpr {
+                source: source_json(tcx, def_id, *span),
+                def_id: hash_id(tcx, def_id),
                 depth: *depth,
+                kind: kind.to_string(),
+                chained: *chained,
+                scrutinee: scrutinee.to_string(),
+                has_else: *has_else,
             },
-            Block::Unsafe { def_id, lines, depth } => BlockJson::Unsafe {
-                def_id: hash_id(def_id),
+            Block::Unsafe { def_id, span, lines, depth } => BlockJson::Unsafe {
+                source: source_json(tcx, def_id, *span),
+                def_id: hash_id(tcx, def_id),
                 lines: *lines,
                 depth: *depth,
             },
-            Block::Iter { def_id, depth, methods } => BlockJson::Iter {
-                def_id: hash_id(def_id),
+            Block::Iter { def_id, span, depth, methods, origin, origin_method, adapters, terminal, collect_type } => BlockJson::Iter {
+                source: source_json(tcx, def_id, *span),
+                def_id: hash_id(tcx, def_id),
                 depth: *depth,
                 methods: methods.clone(),
+                origin: origin.to_string(),
+                origin_method: origin_method.clone(),
+                adapters: adapters.clone(),
+                terminal: terminal.clone(),
+                collect_type: collect_type.clone(),
             },
-            Block::Def { params, ret, unsafety, recursive, lines } => BlockJson::Def {
+            Block::Def { span, def_id, params, ret, unsafety, recursive, lines } => BlockJson::Def {
+                source: SourceJson {
+                    ty: identifiers().readable().then(|| tcx.fn_sig(*def_id).instantiate_identity().to_string()),
+                    ..source_json(tcx, def_id, *span)
+                },
                 params: serde_json::to_value(params).unwrap(),
                 ret: serde_json::to_value(ret).unwrap(),
                 unsafety: *unsafety,
                 recursive: *recursive,
                 lines: *lines,
             },
-            Block::NoType { def_id, lines , depth} => BlockJson::NoType {
-                def_id: hash_id(def_id),
+            Block::NoType { def_id, span, lines , depth, type_error } => BlockJson::NoType {
+                source: source_json(tcx, def_id, *span),
+                def_id: hash_id(tcx, def_id),
+                lines: *lines,
+                depth: *depth,
+                type_error: *type_error,
+            },
+            Block::Closure { def_id, span, lines, depth, is_move, captures, escapes } => BlockJson::Closure {
+                source: source_json(tcx, def_id, *span),
+                def_id: hash_id(tcx, def_id),
                 lines: *lines,
                 depth: *depth,
+                is_move: *is_move,
+                captures: captures.clone(),
+                escapes: escapes.clone(),
             }
         }
     }
@@ -173,14 +317,15 @@ pub struct HirVisitor<'tcx> {
     loops: Vec<Block>,
     matches: Vec<Block>,
     let_exprs: Vec<Block>,
-    iter_mthds: HashMap<OwnerId, Block>,
+    iter_mthds: Vec<Block>,
     calls: HashMap<DefId, HashMap<DefId, u32>>,
     unsafe_blocks: Vec<Block>,
     no_type: Vec<Block>,
+    closures: Vec<Block>,
 }
 
 // json version of visitor for serialization
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
 pub struct VisitorJson {
     pub(crate) fns: HashMap<String, BlockJson>,
     pub(crate) loops: Vec<BlockJson>,
@@ -190,6 +335,55 @@ pub struct VisitorJson {
     pub(crate) calls: HashMap<String, HashMap<String, u32>>,
     pub(crate) unsafe_blocks: Vec<BlockJson>,
     pub(crate) no_type: Vec<BlockJson>,
+    pub(crate) closures: Vec<BlockJson>,
+    pub(crate) idioms: Vec<IdiomJson>,
+}
+
+// what the visitor finds in one owner (an item, impl item, trait item or foreign item),
+// the unit results are cached in
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub(crate) struct OwnerJson {
+    def: Option<BlockJson>,
+    loops: Vec<BlockJson>,
+    matches: Vec<BlockJson>,
+    let_exprs: Vec<BlockJson>,
+    iter_mthds: Vec<BlockJson>,
+    // how often the owner calls each local fn
+    calls: HashMap<String, u32>,
+    unsafe_blocks: Vec<BlockJson>,
+    no_type: Vec<BlockJson>,
+    closures: Vec<BlockJson>,
+    idioms: Vec<IdiomJson>,
+}
+
+impl VisitorJson {
+    // adds what was found in the owner with id `owner`
+    fn add_owner(&mut self, owner: String, json: OwnerJson) {
+        for (callee, count) in json.calls {
+            self.calls.entry(callee).or_default().insert(owner.clone(), count);
+        }
+        if let Some(def) = json.def {
+            self.fns.insert(owner, def);
+        }
+        self.loops.extend(json.loops);
+        self.matches.extend(json.matches);
+        self.let_exprs.extend(json.let_exprs);
+        self.iter_mthds.extend(json.iter_mthds);
+        self.unsafe_blocks.extend(json.unsafe_blocks);
+        self.no_type.extend(json.no_type);
+        self.closures.extend(json.closures);
+        self.idioms.extend(json.idioms);
+    }
+}
+
+// visits every owner of the crate, reusing the cached results of owners whose HIR didn't change
+pub(crate) fn visit_crate(tcx: TyCtxt, cache: &mut OwnerCache) -> VisitorJson {
+    let mut visit = VisitorJson::default();
+    for owner in tcx.hir_crate_items(()).owners() {
+        let json = cache.owner(tcx, owner, || HirVisitor::visit_owner(tcx, owner));
+        visit.add_owner(hash_id(tcx, &owner.to_def_id()), json);
+    }
+    visit
 }
 
 impl<'tcx> HirVisitor<'tcx> {
@@ -202,34 +396,56 @@ impl<'tcx> HirVisitor<'tcx> {
             loops: Vec::new(),
             matches: Vec::new(),
             let_exprs: Vec::new(),
-            iter_mthds: HashMap::new(),
+            iter_mthds: Vec::new(),
             calls: HashMap::new(),
             unsafe_blocks: Vec::new(),
             no_type: Vec::new(),
+            closures: Vec::new(),
+        }
+    }
+
+    // whether the owner's body type-checked without errors
+    fn type_checked(&self, owner: OwnerId) -> bool {
+        self.tcx.typeck(owner.def_id).tainted_by_errors.is_none()
+    }
+
+    // visits one owner's HIR and bodies, leaving the items nested in it to their own owners
+    fn visit_owner(tcx: TyCtxt<'tcx>, owner: OwnerId) -> OwnerJson {
+        let mut visitor = HirVisitor::new(tcx);
+        match tcx.hir_owner_node(owner) {
+            OwnerNode::Item(item) => visitor.visit_item(item),
+            OwnerNode::ImplItem(item) => visitor.visit_impl_item(item),
+            OwnerNode::TraitItem(item) => visitor.visit_trait_item(item),
+            OwnerNode::ForeignItem(item) => visitor.visit_foreign_item(item),
+            OwnerNode::Crate(..) | OwnerNode::Synthetic => {}
+        }
+        OwnerJson {
+            idioms: idioms::owner_idioms(tcx, owner).iter().map(|idiom| idiom.to_json(tcx)).collect(),
+            ..visitor.to_json()
         }
     }
 
-    pub fn to_json(&self) -> VisitorJson {
-        VisitorJson {
-            fns: self.fns.iter().map(|(k, v)| (hash_id(k), v.to_json())).collect(),
-            loops: self.loops.iter().map(|v| v.to_json()).collect(),
-            matches: self.matches.iter().map(|v| v.to_json()).collect(),
-            let_exprs: self.let_exprs.iter().map(|v| v.to_json()).collect(),
-            iter_mthds: self.iter_mthds.values().map(|v| v.to_json()).collect(),
+    fn to_json(&self) -> OwnerJson {
+        OwnerJson {
+            def: self.fns.values().next().map(|v| v.to_json(self.tcx)),
+            loops: self.loops.iter().map(|v| v.to_json(self.tcx)).collect(),
+            matches: self.matches.iter().map(|v| v.to_json(self.tcx)).collect(),
+            let_exprs: self.let_exprs.iter().map(|v| v.to_json(self.tcx)).collect(),
+            iter_mthds: self.iter_mthds.iter().map(|v| v.to_json(self.tcx)).collect(),
             calls: self.calls.iter()
-                .map(|(k, v)| (hash_id(k), v.iter()
-                    .map(|(from, cnt)| (hash_id(from), *cnt))
-                    .collect::<HashMap<_, _>>()))
-                    .collect(),
-            unsafe_blocks: self.unsafe_blocks.iter().map(|v| v.to_json()).collect(),
-            no_type: self.no_type.iter().map(|v| v.to_json()).collect(),
+                .map(|(k, v)| (hash_id(self.tcx, k), v.values().sum()))
+                .collect(),
+            unsafe_blocks: self.unsafe_blocks.iter().map(|v| v.to_json(self.tcx)).collect(),
+            no_type: self.no_type.iter().map(|v| v.to_json(self.tcx)).collect(),
+            closures: self.closures.iter().map(|v| v.to_json(self.tcx)).collect(),
+            idioms: Vec::new(),
         }
     }
 }
 
 // - `Visitor::nested_visit_map` becomes `Visitor::maybe_tcx`.
 impl<'tcx> Visitor<'tcx> for HirVisitor<'tcx> {
-    type NestedFilter = nested_filter::All;
+    type NestedFilter = nested_filter::OnlyBodies;
     fn maybe_tcx(&mut self) -> Self::MaybeTyCtxt {
         self.tcx
     }
@@ -240,11 +456,13 @@ impl<'tcx> Visitor<'tcx> for HirVisitor<'tcx> {
         let def_id = item.owner_id.to_def_id();
         if self.tcx.def_kind(def_id) == DefKind::Fn {
             match item.kind {
-                ItemKind::Fn { sig, body, .. } => {  //todo: check for body_id?
+                ItemKind::Fn { sig, body, .. } if self.type_checked(item.owner_id) => {  //todo: check for body_id?
                     let unsafety = sig.header.safety == rustc_hir::HeaderSafety::Normal(rustc_hir::Safety::Unsafe);
                     let params = visit_params(self.tcx, body);
                     let ret = visit_return(self.tcx, body);
                     self.fns.insert(def_id, Block::Def {
+                        span: item.span,
+                        def_id,
                         params,
                         ret,
                         unsafety,
@@ -273,16 +491,20 @@ impl<'tcx> Visitor<'tcx> for HirVisitor<'tcx> {
     // for finding the ImplItemKind::Fn
     fn visit_impl_item(&mut self, item: &'tcx rustc_hir::ImplItem<'tcx>) {
         if let rustc_hir::ImplItemKind::Fn(sig, body_id) = item.kind {
-            let unsafety = sig.header.safety == rustc_hir::HeaderSafety::Normal(rustc_hir::Safety::Unsafe);
-            let params = visit_params(self.tcx, body_id);
-            let ret = visit_return(self.tcx, body_id);
-            self.fns.insert(item.owner_id.to_def_id(), Block::Def {
-                params,
-                ret,
-                unsafety,
-                recursive: false,
-                lines: line_count(self.source_map, item.span),
-            });
+            if self.type_checked(item.owner_id) {
+                let unsafety = sig.header.safety == rustc_hir::HeaderSafety::Normal(rustc_hir::Safety::Unsafe);
+                let params = visit_params(self.tcx, body_id);
+                let ret = visit_return(self.tcx, body_id);
+                self.fns.insert(item.owner_id.to_def_id(), Block::Def {
+                    span: item.span,
+                    def_id: item.owner_id.to_def_id(),
+                    params,
+                    ret,
+                    unsafety,
+                    recursive: false,
+                    lines: line_count(self.source_map, item.span),
+                });
+            }
         }
         intravisit::walk_impl_item(self, item);
     }
@@ -291,10 +513,15 @@ impl<'tcx> Visitor<'tcx> for HirVisitor<'tcx> {
     fn visit_trait_item(&mut self, item: &'tcx rustc_hir::TraitItem<'tcx>){
         if let rustc_hir::TraitItemKind::Fn(sig, tf) = item.kind {
             let unsafety = sig.header.safety == rustc_hir::HeaderSafety::Normal(rustc_hir::Safety::Unsafe);
-            if let rustc_hir::TraitFn::Provided(body_id) = tf{
+            if let rustc_hir::TraitFn::Provided(body_id) = tf {
+                if !self.type_checked(item.owner_id) {
+                    return intravisit::walk_trait_item(self, item);
+                }
                 let params = visit_params(self.tcx, body_id);
                 let ret = visit_return(self.tcx, body_id);
                 self.fns.insert(item.owner_id.to_def_id(), Block::Def {
+                    span: item.span,
+                    def_id: item.owner_id.to_def_id(),
                     params,
                     ret,
                     unsafety,
@@ -312,6 +539,7 @@ impl<'tcx> Visitor<'tcx> for HirVisitor<'tcx> {
             let def_id = block.hir_id.owner.to_def_id();
             self.unsafe_blocks.push(Block::Unsafe {
                 def_id,
+                span: block.span,
                 lines: line_count(self.source_map, block.span),
                 depth: self.depth,
             });
@@ -341,47 +569,124 @@ impl<'tcx> Visitor<'tcx> for HirVisitor<'tcx> {
         }
     }
 
-    // analyze loops, matches, let expressions, function calls, and iter method calls
+    // `let ... else` statements, recorded with the `let` expressions
+    fn visit_local(&mut self, local: &'tcx LetStmt<'tcx>) {
+        if let (Some(init), Some(_)) = (local.init, local.els) {
+            let typeck_results = self.tcx.typeck(local.hir_id.owner);
+            self.let_exprs.push(Block::LetExpr {
+                def_id: local.hir_id.owner.to_def_id(),
+                span: local.span,
+                depth: self.depth,
+                kind: "LetElse",
+                chained: false,
+                scrutinee: scrutinee_category(self.tcx, typeck_results.expr_ty(init).peel_refs()),
+                has_else: true,
+            });
+        }
+        intravisit::walk_local(self, local)
+    }
+
+    // analyze loops, matches, let expressions, function calls, closures, and iter method calls
     fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
 
         let owner = expr.hir_id.owner;
         if !self.tcx.has_typeck_results(owner.def_id) {
             self.no_type.push(Block::NoType {
                 def_id: owner.to_def_id(),
+                span: expr.span,
                 lines: line_count(self.source_map, expr.span),
                 depth: self.depth,
+                type_error: false,
             });
             return
         }
         let typeck_results = self.tcx.typeck(owner);
+        // the types of a body with type errors can't be trusted, so none of it is visited
+        if typeck_results.tainted_by_errors.is_some() {
+            self.no_type.push(Block::NoType {
+                def_id: owner.to_def_id(),
+                span: expr.span,
+                lines: line_count(self.source_map, expr.span),
+                depth: self.depth,
+                type_error: true,
+            });
+            return
+        }
 
         let hir_id = expr.hir_id;
         let def_id = hir_id.owner.to_def_id();
         match expr.kind {
-            ExprKind::Loop(..) => {
+            ExprKind::Loop(block, label, source, _) => {
+                let (kind, iterates) = match source {
+                    LoopSource::Loop => ("Loop", None),
+                    LoopSource::While if is_while_let(block) => ("WhileLet", None),
+                    LoopSource::While => ("While", None),
+                    LoopSource::ForLoop => {
+                        let head = for_loop_head(self.tcx, expr);
+                        ("For", head.map(|head| iterated_category(self.tcx, typeck_results.expr_ty(head), head)))
+                    }
+                };
                 self.loops.push(Block::Loop {
                     def_id,
+                    hir_id,
+                    span: expr.span,
                     lines: line_count(self.source_map, expr.span),
                     depth: self.depth,
+                    kind,
+                    labeled: label.is_some(),
+                    breaks: 0,
+                    continues: 0,
+                    break_with_value: false,
+                    iterates,
                 });
             },
-            ExprKind::Match(.., arms, src) => {
+            // the `break`s that `while` and `for` loops are lowered with aren't counted
+            ExprKind::Break(destination, value) if expr.span.desugaring_kind().is_none() => {
+                if let Some(Block::Loop { breaks, break_with_value, .. }) = self.target_loop(destination) {
+                    *breaks += 1;
+                    *break_with_value |= value.is_some();
+                }
+            }
+            ExprKind::Continue(destination) => {
+                if let Some(Block::Loop { continues, .. }) = self.target_loop(destination) {
+                    *continues += 1;
+                }
+            }
+            ExprKind::Match(scrutinee, arms, src) => {
                 if src == MatchSource::Normal {
+                    let scrutinee_ty = typeck_results.expr_ty(scrutinee).peel_refs();
                     self.matches.push(Block::Match {
                         def_id,
+                        span: expr.span,
                         lines: line_count(self.source_map, expr.span),
                         arms: arms.len() as u32,
                         depth: self.depth,
+                        scrutinee: scrutinee_category(self.tcx, scrutinee_ty),
+                        arm_patterns: arms.iter().map(arm_json).collect(),
+                        all_variants_listed: match scrutinee_ty.kind() {
+                            TyKind::Adt(adt, _) if adt.is_enum() => Some(all_variants_listed(typeck_results, *adt, arms)),
+                            _ => None,
+                        },
                     });
                 }
             },
-            ExprKind::Let(_) => {
+            ExprKind::Let(let_expr) => {
+                let (kind, chained, has_else) = let_condition(self.tcx, expr);
                 self.let_exprs.push(Block::LetExpr {
                     def_id,
+                    span: expr.span,
                     depth: self.depth,
+                    kind,
+                    chained,
+                    scrutinee: scrutinee_category(self.tcx, typeck_results.expr_ty(let_expr.init).peel_refs()),
+                    has_else,
                 });
             },
-            ExprKind::Call(func, ..) => {
+            ExprKind::Call(func, args) => {
+                // `for` loops call `IntoIterator::into_iter` on their head
+                if let (ExprKind::Path(QPath::LangItem(LangItem::IntoIterIntoIter, _)), [head]) = (func.kind, args) {
+                    self.record_pipeline(typeck_results, head, true);
+                }
                 if let ExprKind::Path(qpath) = func.kind {
                     if let Some(call_def_id) = typeck_results
                         .qpath_res(&qpath, func.hir_id)
@@ -404,38 +709,251 @@ impl<'tcx> Visitor<'tcx> for HirVisitor<'tcx> {
                     }
                 }
             }
-            ExprKind::MethodCall(
-                segment,
-                receiver,
-                _params,
-                _span) => {
-                let method_name = segment.ident.to_string();
-                let receiver_type = typeck_results.expr_ty(receiver);
-
-                // does receiver type implement iter trait?
-                if ty_impls_iter(self.tcx, receiver_type, expr) {
-                    let owner = expr.hir_id.owner;
-                    if self.iter_mthds.contains_key(&owner) {
-                        if let Block::Iter { methods, .. } 
-                                = self.iter_mthds.get_mut(&owner).unwrap() {
-                            methods.push(method_name);
-                        }
-                    }
-                    else {
-                        self.iter_mthds.insert(owner, Block::Iter {
-                            def_id,
-                            depth: self.depth,
-                            methods: vec![method_name],
-                        });
-                    }
+            ExprKind::MethodCall(_, receiver, ..) => {
+                // each chain is recorded once, from its last call
+                if ends_pipeline(self.tcx, typeck_results, expr, receiver) {
+                    self.record_pipeline(typeck_results, expr, false);
                 }
             }
+            ExprKind::Closure(closure) if closure.kind == ClosureKind::Closure => {
+                let captures = typeck_results
+                    .closure_min_captures_flattened(closure.def_id)
+                    .map(|capture| (capture.to_string(self.tcx), capture_kind(capture.info.capture_kind)))
+                    .collect();
+                self.closures.push(Block::Closure {
+                    def_id,
+                    span: expr.span,
+                    lines: line_count(self.source_map, expr.span),
+                    depth: self.depth,
+                    is_move: matches!(closure.capture_clause, CaptureBy::Value { .. }),
+                    captures,
+                    escapes: closure_escape(self.tcx, typeck_results, expr),
+                });
+            }
             _ => {}
         }
         intravisit::walk_expr(self, expr);
     }
 }
 
+impl<'tcx> HirVisitor<'tcx> {
+    // the loop a `break` or `continue` leaves, unless it leaves a labeled block
+    fn target_loop(&mut self, destination: Destination) -> Option<&mut Block> {
+        let target = destination.target_id.ok()?;
+        self.loops.iter_mut().rev().find(|block| matches!(block, Block::Loop { hir_id, .. } if *hir_id == target))
+    }
+
+    // records the iterator chain ending at `last`, walking back through its receivers to where
+    // the iterator comes from. `for_loop` is set when `last` is the head of a for loop.
+    fn record_pipeline(&mut self, typeck_results: &TypeckResults<'tcx>, last: &'tcx Expr<'tcx>, for_loop: bool) {
+        let tcx = self.tcx;
+        let is_iter = |expr: &Expr<'tcx>| ty_impls_iter(tcx, typeck_results.expr_ty(expr), expr);
+        let mut methods = Vec::new();
+        let mut expr = last;
+        let (origin, origin_method) = loop {
+            match expr.kind {
+                ExprKind::MethodCall(segment, receiver, ..) if is_iter(receiver) => {
+                    methods.push(segment.ident.to_string());
+                    expr = receiver;
+                }
+                // a collection a for loop iterates, eg. `&v`
+                _ if !is_iter(expr) => break ("IntoIterator", None),
+                // a method turning a value into an iterator, eg. `v.iter()` or `s.chars()`
+                ExprKind::MethodCall(segment, ..) => break ("IntoIterator", Some(segment.ident.to_string())),
+                _ if is_range(tcx, typeck_results.expr_ty(expr)) => break ("Range", None),
+                _ => break ("Custom", None),
+            }
+        };
+        methods.reverse();
+
+        // a chain that still is an iterator at its end is returned, stored or passed on
+        let consumed = !for_loop && !is_iter(last);
+        let terminal = if for_loop { Some("for".to_string()) } else if consumed { methods.last().cloned() } else { None };
+        let adapters = methods[..methods.len() - consumed as usize].to_vec();
+        let collect_type = (terminal.as_deref() == Some("collect"))
+            .then(|| collect_type(tcx, typeck_results.expr_ty(last)));
+        self.iter_mthds.push(Block::Iter {
+            def_id: last.hir_id.owner.to_def_id(),
+            span: last.span,
+            depth: self.depth,
+            methods,
+            origin,
+            origin_method,
+            adapters,
+            terminal,
+            collect_type,
+        });
+    }
+}
+
+// whether a method call ends an iterator chain: it is called on an iterator or returns one, and
+// isn't continued by another method or iterated by a for loop
+fn ends_pipeline<'tcx>(tcx: TyCtxt<'tcx>, typeck_results: &TypeckResults<'tcx>, expr: &Expr<'tcx>, receiver: &Expr<'tcx>) -> bool {
+    if !ty_impls_iter(tcx, typeck_results.expr_ty(expr), expr) {
+        return ty_impls_iter(tcx, typeck_results.expr_ty(receiver), receiver);
+    }
+    let Node::Expr(parent) = tcx.parent_hir_node(expr.hir_id) else { return true };
+    match parent.kind {
+        ExprKind::MethodCall(_, parent_receiver, ..) => parent_receiver.hir_id != expr.hir_id,
+        ExprKind::Call(func, _) => !matches!(func.kind, ExprKind::Path(QPath::LangItem(LangItem::IntoIterIntoIter, _))),
+        _ => true,
+    }
+}
+
+// what a match is on, with references peeled off
+fn scrutinee_category(tcx: TyCtxt, ty: Ty) -> &'static str {
+    match ty.kind() {
+        TyKind::Adt(adt, _) if tcx.is_diagnostic_item(sym::Option, adt.did()) => "Option",
+        TyKind::Adt(adt, _) if tcx.is_diagnostic_item(sym::Result, adt.did()) => "Result",
+        TyKind::Adt(adt, _) if tcx.is_diagnostic_item(sym::String, adt.did()) => "String",
+        TyKind::Adt(adt, _) if adt.is_enum() => "Enum",
+        TyKind::Adt(..) => "Struct",
+        TyKind::Int(_) | TyKind::Uint(_) => "Int",
+        TyKind::Str => "String",
+        TyKind::Bool => "Bool",
+        TyKind::Char => "Char",
+        TyKind::Tuple(_) => "Tuple",
+        TyKind::Array(..) | TyKind::Slice(_) => "Slice",
+        _ => "Other",
+    }
+}
+
+fn arm_json(arm: &Arm) -> ArmJson {
+    let mut json = ArmJson {
+        wildcard: matches!(arm.pat.kind, PatKind::Wild),
+        guard: arm.guard.is_some(),
+        ..ArmJson::default()
+    };
+    arm.pat.walk(|pat| {
+        match pat.kind {
+            PatKind::Binding(.., None) => json.bindings += 1,
+            PatKind::Binding(.., Some(_)) => json.at_bindings += 1,
+            PatKind::Or(_) => json.or_pattern = true,
+            PatKind::Slice(..) => json.slice = true,
+            PatKind::Box(_) => json.box_pattern = true,
+            PatKind::Range(..) => json.range = true,
+            _ => {}
+        }
+        true
+    });
+    json
+}
+
+// whether every variant of the enum has an arm naming it, rather than some being left
+// to a wildcard or a binding
+fn all_variants_listed<'tcx>(typeck_results: &TypeckResults<'tcx>, adt: AdtDef<'tcx>, arms: &[Arm]) -> bool {
+    let mut listed = HashSet::new();
+    for arm in arms {
+        listed_variants(typeck_results, adt, arm.pat, &mut listed);
+    }
+    adt.variants().iter().all(|variant| listed.contains(&variant.def_id))
+}
+
+// the variants a pattern names at its top, through or-patterns, `@` bindings and references
+fn listed_variants<'tcx>(typeck_results: &TypeckResults<'tcx>, adt: AdtDef<'tcx>, pat: &Pat, listed: &mut HashSet<DefId>) {
+    let res = match pat.kind {
+        PatKind::Or(pats) => {
+            for pat in pats {
+                listed_variants(typeck_results, adt, pat, listed);
+            }
+            return;
+        }
+        PatKind::Binding(.., Some(pat)) | PatKind::Ref(pat, _) => return listed_variants(typeck_results, adt, pat, listed),
+        PatKind::Struct(qpath, ..) | PatKind::TupleStruct(qpath, ..) => typeck_results.qpath_res(&qpath, pat.hir_id),
+        PatKind::Expr(PatExpr { hir_id, kind: PatExprKind::Path(qpath), .. }) => typeck_results.qpath_res(qpath, *hir_id),
+        _ => return,
+    };
+    if matches!(res, Res::Def(DefKind::Variant | DefKind::Ctor(CtorOf::Variant, _), _)) {
+        listed.insert(adt.variant_of_res(res).def_id);
+    }
+}
+
+// whether a `let` expression is the condition of an `if` (or an `if let` guard) or a `while`,
+// whether it is chained with `&&`, and whether its `if` has an `else` branch
+fn let_condition(tcx: TyCtxt, let_expr: &Expr) -> (&'static str, bool, bool) {
+    let mut chained = false;
+    let mut hir_id = let_expr.hir_id;
+    loop {
+        let Node::Expr(parent) = tcx.parent_hir_node(hir_id) else { return ("IfLet", chained, false) };
+        match parent.kind {
+            ExprKind::Binary(op, ..) if op.node == BinOpKind::And => chained = true,
+            ExprKind::DropTemps(_) => {}
+            // the `else { break }` a `while` loop is lowered with isn't the user's
+            ExprKind::If(_, _, els) => {
+                let in_while = matches!(tcx.parent_hir_node(parent.hir_id), Node::Block(block)
+                    if matches!(tcx.parent_hir_node(block.hir_id), Node::Expr(Expr { kind: ExprKind::Loop(_, _, LoopSource::While, _), .. })));
+                return if in_while { ("WhileLet", chained, false) } else { ("IfLet", chained, els.is_some()) };
+            }
+            _ => return ("IfLet", chained, false),
+        }
+        hir_id = parent.hir_id;
+    }
+}
+
+// a `while` loop is lowered to `loop { if cond { body } else { break } }`, keeping its `let`s in `cond`
+fn is_while_let(body: &rustc_hir::Block) -> bool {
+    let Some(Expr { kind: ExprKind::If(cond, ..), .. }) = body.expr else { return false };
+    has_let(cond)
+}
+
+// whether a condition is a `let`, or a let chain like `let Some(x) = a && x > 0`
+fn has_let(cond: &Expr) -> bool {
+    match cond.kind {
+        ExprKind::Let(_) => true,
+        ExprKind::Binary(op, lhs, rhs) if op.node == BinOpKind::And => has_let(lhs) || has_let(rhs),
+        ExprKind::DropTemps(inner) => has_let(inner),
+        _ => false,
+    }
+}
+
+// a `for` loop is lowered to `match IntoIterator::into_iter(head) { mut iter => loop { .. } }`
+pub(crate) fn for_loop_head<'tcx>(tcx: TyCtxt<'tcx>, loop_expr: &Expr<'tcx>) -> Option<&'tcx Expr<'tcx>> {
+    let Node::Arm(arm) = tcx.parent_hir_node(loop_expr.hir_id) else { return None };
+    let Node::Expr(Expr { kind: ExprKind::Match(scrutinee, _, MatchSource::ForLoopDesugar), .. }) = tcx.parent_hir_node(arm.hir_id) else {
+        return None
+    };
+    match scrutinee.kind {
+        ExprKind::Call(_, [head]) => Some(head),
+        _ => None,
+    }
+}
+
+// what a for loop iterates: a range, a slice, array or `Vec` by reference, mutable reference
+// or value, another iterator, or some other `IntoIterator` like a `HashMap`
+fn iterated_category<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>, head: &Expr<'tcx>) -> &'static str {
+    let is_sequence = |ty: Ty| match ty.kind() {
+        TyKind::Slice(_) | TyKind::Array(..) => true,
+        TyKind::Adt(adt, _) => tcx.is_diagnostic_item(sym::Vec, adt.did()),
+        _ => false,
+    };
+    match ty.kind() {
+        _ if is_range(tcx, ty) => "Range",
+        TyKind::Ref(_, inner, Mutability::Not) if is_sequence(*inner) => "Ref",
+        TyKind::Ref(_, inner, Mutability::Mut) if is_sequence(*inner) => "RefMut",
+        _ if is_sequence(ty) => "Value",
+        _ if ty_impls_iter(tcx, ty, head) => "Iterator",
+        _ => "Other",
+    }
+}
+
+// whether ty is a range that iterates, eg. `0..n` or `0..=n`
+pub(crate) fn is_range(tcx: TyCtxt, ty: Ty) -> bool {
+    let TyKind::Adt(adt, _) = ty.kind() else { return false };
+    [LangItem::Range, LangItem::RangeFrom, LangItem::RangeInclusiveStruct]
+        .into_iter()
+        .any(|item| tcx.is_lang_item(adt.did(), item))
+}
+
+// the type an iterator is collected into: the path of library types like `Vec`,
+// and the crate's own types hashed like other ids
+fn collect_type(tcx: TyCtxt, ty: Ty) -> String {
+    match ty.kind() {
+        TyKind::Adt(adt, _) if adt.did().is_local() => hash_id(tcx, &adt.did()),
+        TyKind::Adt(adt, _) => tcx.def_path_str(adt.did()),
+        kind => ty_kind_variant(kind),
+    }
+}
+
 // analyze param tykinds, mutability, and optional closure traits
 fn visit_params(tcx: TyCtxt, body_id: BodyId) -> Params {
     let mut closure_traits = Vec::new();
@@ -572,8 +1090,85 @@ fn is_dyn_closure(tcx: TyCtxt, ty: &Ty) -> Option<String> {
     }
 }
 
+// converts how a closure captures a place to a string
+fn capture_kind(capture: UpvarCapture) -> String {
+    match capture {
+        UpvarCapture::ByValue => "ByValue".to_string(),
+        UpvarCapture::ByRef(BorrowKind::Immutable) => "ByRefShared".to_string(),
+        UpvarCapture::ByRef(BorrowKind::Mutable) => "ByRefMut".to_string(),
+        UpvarCapture::ByRef(BorrowKind::UniqueImmutable) => "ByRefUnique".to_string(),
+    }
+}
+
+// checks if a closure outlives its function, either returned or handed to a call whose signature
+// needs it to be `'static`, like `thread::spawn`
+fn closure_escape<'tcx>(tcx: TyCtxt<'tcx>, typeck_results: &TypeckResults<'tcx>, closure: &Expr<'tcx>) -> Option<String> {
+    let closure_ty = typeck_results.expr_ty(closure);
+    let body = tcx.hir_body_owned_by(closure.hir_id.owner.def_id);
+    if typeck_results.expr_ty(body.value).walk().any(|arg| arg.as_type() == Some(closure_ty)) {
+        return Some("Return".to_string());
+    }
+
+    // only blocks, casts and calls that return their argument, like `Box::new`, keep the closure as the value
+    let mut child = closure.hir_id;
+    for (hir_id, node) in tcx.hir_parent_iter(closure.hir_id) {
+        let Node::Expr(parent) = node else {
+            if matches!(node, Node::Block(..)) { child = hir_id; continue } else { break }
+        };
+        let argument = match parent.kind {
+            ExprKind::Ret(..) => return Some("Return".to_string()),
+            ExprKind::Call(func, args) => match typeck_results.expr_ty(func).kind() {
+                TyKind::FnDef(callee, _) => args.iter().position(|arg| arg.hir_id == child).map(|idx| (*callee, idx)),
+                _ => None,
+            },
+            // the receiver is the method's first input
+            ExprKind::MethodCall(_, _, args, _) => typeck_results
+                .type_dependent_def_id(parent.hir_id)
+                .zip(args.iter().position(|arg| arg.hir_id == child))
+                .map(|(callee, idx)| (callee, idx + 1)),
+            ExprKind::Block(..) | ExprKind::Cast(..) | ExprKind::DropTemps(..) => {
+                child = hir_id;
+                continue;
+            }
+            _ => None,
+        };
+        let Some((callee, idx)) = argument else { break };
+        match argument_escape(tcx, callee, idx) {
+            Some(escape) => return Some(escape.to_string()),
+            None if returns_argument(tcx, callee, idx) => child = hir_id,
+            None => break,
+        }
+    }
+    None
+}
+
+// `Spawn` when the callee's input at `idx` has to be `'static`, a type parameter bound
+// by `'static` or a trait object like `Box<dyn FnOnce()>`
+fn argument_escape(tcx: TyCtxt, callee: DefId, idx: usize) -> Option<&'static str> {
+    let sig = tcx.fn_sig(callee).instantiate_identity().skip_binder();
+    let input = *sig.inputs().get(idx)?;
+    let bounded = tcx.predicates_of(callee).instantiate_identity(tcx).predicates.iter().any(|clause| {
+        clause
+            .as_type_outlives_clause()
+            .is_some_and(|outlives| outlives.skip_binder().0 == input && outlives.skip_binder().1.is_static())
+    });
+    let object = input.walk().any(|arg| {
+        matches!(arg.as_type().map(|ty| *ty.kind()), Some(TyKind::Dynamic(_, region, _)) if region.is_static())
+    });
+    (bounded || object).then_some("Spawn")
+}
+
+// whether the callee's return type holds its input at `idx`, like `Box::new` returning `Box<T>` for `T`
+fn returns_argument(tcx: TyCtxt, callee: DefId, idx: usize) -> bool {
+    let sig = tcx.fn_sig(callee).instantiate_identity().skip_binder();
+    match sig.inputs().get(idx) {
+        Some(input) if matches!(input.kind(), TyKind::Param(_)) => sig.output().walk().any(|arg| arg.as_type() == Some(*input)),
+        _ => false,
+    }
+}
+
 // checks if ty implements the Iterator trait
-fn ty_impls_iter<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>, expr: &Expr<'tcx>) -> bool {
+pub(crate) fn ty_impls_iter<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>, expr: &Expr<'tcx>) -> bool {
     if let Some(iterator_trait_def_id) = tcx.lang_items().iterator_trait() {
         if ty.does_implement_trait(
             tcx,
@@ -585,6 +1180,35 @@ fn ty_impls_iter<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>, expr: &Expr<'tcx>) -> bo
     false
 }
 
+// the file of a span, hashed like the extension hashes file names unless only paths are asked for
+pub(crate) fn source_json(tcx: TyCtxt, def_id: &DefId, span: Span) -> SourceJson {
+    let source_map = tcx.sess.source_map();
+    let identifiers = identifiers();
+    // blocks written inside a macro call are attributed to the call
+    let span = span.source_callsite();
+    let path = source_path(tcx, span);
+    SourceJson {
+        file: if identifiers.hashed() { hash_file_name(&path) } else { path },
+        line_start: source_map.lookup_char_pos(span.lo()).line,
+        line_end: source_map.lookup_char_pos(span.hi()).line,
+        span: identifiers.readable().then(|| SpanJson::new(source_map, span)),
+        def_path: identifiers.readable().then(|| tcx.def_path_str(*def_id)),
+        ty: None,
+    }
+}
+
+// the absolute path of the span's file, which is the name the editor gives it
+fn source_path(tcx: TyCtxt, span: Span) -> String {
+    let file = tcx.sess.source_map().lookup_source_file(span.lo());
+    match &file.name {
+        FileName::Real(name) => match name.local_path() {
+            Some(path) => tcx.sess.opts.working_dir.local_path_if_available().join(path).display().to_string(),
+            None => name.remapped_path_if_available().display().to_string(),
+        },
+        name => name.prefer_local().to_string(),
+    }
+}
+
 // calculates the number of lines in a span
 fn line_count(source_map: &SourceMap, span: rustc_span::Span) -> usize {
     let start = source_map.lookup_char_pos(span.lo()).line;
@@ -592,19 +1216,6 @@ fn line_count(source_map: &SourceMap, span: rustc_span::Span) -> usize {
     end - start + 1
 }
 
-// hashes the DefId to an anonymized string
-fn hash_id(input: &DefId) -> String {
-    if HASH_EN {
-        let mut hasher = DefaultHasher::new();
-        input.hash(&mut hasher);
-        let hash_value = hasher.finish();
-        format!("{}", hash_value).to_string()
-    }
-    else {
-        format!("{:?}", input).to_string()
-    }
-}
-
 // converts TyKind variant to a string
 fn ty_kind_variant(ty_kind: &TyKind) -> String {
     match ty_kind {